@@ -8,11 +8,28 @@
 
 use anyhow::{Context, Result};
 use clap::{Parser, Subcommand};
+use ethers::signers::{LocalWallet, Signer};
+use ethers::types::{Address, Signature, U256};
+use ethers::utils::{keccak256, to_checksum};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
 use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::path::PathBuf;
 use std::time::{SystemTime, UNIX_EPOCH};
 
+/// Regenerate cached compliance proofs this long before they expire
+const PROOF_REFRESH_MARGIN_SECS: u64 = 24 * 60 * 60;
+
+/// ABI words the compliance circuit commits: agent, valid_until, jurisdiction
+const PUBLIC_VALUES_LEN: usize = 3 * 32;
+
+/// x402-RWA protocol version this agent speaks, requested via `Accept`
+const X402_VERSION: u32 = 1;
+
+/// Claims this agent's credentials can prove; circuits requiring others are refused
+const SUPPORTED_CLAIMS: &[&str] = &["accredited_investor", "not_sanctioned", "kyc_verified"];
+
 #[derive(Parser)]
 #[command(name = "agent")]
 #[command(about = "Autonomous RWA acquisition agent")]
@@ -21,6 +38,26 @@ struct Cli {
     #[arg(long, default_value = "http://localhost:8080")]
     server: String,
     
+    /// Address expected to sign x402 quotes (X-402-Signature)
+    #[arg(long, env = "X402_SERVER_SIGNER")]
+    server_signer: Option<Address>,
+    
+    /// Tolerance over a fresh quote when no --max-price is given (basis points)
+    #[arg(long, default_value = "50")]
+    slippage_bps: u64,
+    
+    /// Directory for cached compliance proofs
+    #[arg(long, default_value = ".x402/proofs")]
+    proof_cache: PathBuf,
+    
+    /// Chain the server must report before the agent buys
+    #[arg(long, env = "CHAIN_ID", default_value = "8453")]
+    chain_id: u64,
+    
+    /// Agent wallet address; derived from AGENT_PRIVATE_KEY when that is set
+    #[arg(long, env = "AGENT_ADDRESS")]
+    address: Option<Address>,
+    
     #[command(subcommand)]
     command: Commands,
 }
@@ -54,13 +91,48 @@ enum Commands {
         /// Dry run (don't actually execute)
         #[arg(long)]
         dry_run: bool,
+        
+        /// Always generate a fresh compliance proof
+        #[arg(long)]
+        no_cache: bool,
+        
+        /// Abort if the challenge price exceeds this many USDC
+        #[arg(long)]
+        max_price: Option<f64>,
+        
+        /// Skip the server health and chain check
+        #[arg(long)]
+        no_preflight: bool,
+        
+        /// Write a JSON settlement receipt to this file
+        #[arg(long)]
+        receipt: Option<PathBuf>,
+    },
+    
+    /// Buy a basket of assets (x402 flow per item)
+    Portfolio {
+        /// JSON file listing [{"asset": ..., "amount": ...}] entries
+        #[arg(short, long)]
+        file: String,
+        
+        /// Dry run (don't actually execute)
+        #[arg(long)]
+        dry_run: bool,
+        
+        /// Always generate a fresh compliance proof
+        #[arg(long)]
+        no_cache: bool,
+        
+        /// Skip the server health and chain check
+        #[arg(long)]
+        no_preflight: bool,
     },
     
     /// Check agent status
     Status {
-        /// Agent address
+        /// Agent address (defaults to this agent's wallet)
         #[arg(short, long)]
-        address: String,
+        address: Option<String>,
     },
 }
 
@@ -68,10 +140,7 @@ enum Commands {
 struct Asset {
     id: String,
     name: String,
-    symbol: String,
-    address: String,
     price_per_unit: u64,
-    currency: String,
     active: bool,
 }
 
@@ -83,22 +152,42 @@ struct Quote {
     total_price: u64,
     fee: u64,
     expiry: u64,
+    expires_in_seconds: u64,
     quote_id: String,
 }
 
+/// Compliance proof persisted between runs
+#[derive(Debug, Serialize, Deserialize)]
+struct CachedProof {
+    circuit_id: String,
+    agent_address: String,
+    proof: String,
+    public_values: String,
+    valid_until: u64,
+}
+
+/// One entry of a portfolio basket file
+#[derive(Debug, Deserialize)]
+struct BasketItem {
+    asset: String,
+    amount: u64,
+}
+
+/// x402 challenge body, cross-checked against the challenge headers
 #[derive(Debug, Deserialize)]
 struct X402Response {
-    error: String,
-    message: String,
     protocol: String,
     asset: String,
     amount: u64,
+    base_price: u64,
+    fee: u64,
     total_price: u64,
     currency: String,
     expiry: u64,
     quote_id: String,
     compliance_circuit: String,
     payment_address: String,
+    chain_id: u64,
 }
 
 #[derive(Debug, Serialize)]
@@ -108,9 +197,10 @@ struct SettlementRequest {
     quote_id: String,
     compliance_proof: String,
     public_values: String,
+    request_signature: String,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Serialize, Deserialize)]
 struct SettlementResponse {
     status: String,
     tx_hash: Option<String>,
@@ -120,6 +210,50 @@ struct SettlementResponse {
     timestamp: u64,
 }
 
+/// Terms of the x402 challenge a purchase settled against
+#[derive(Debug, Serialize, Deserialize)]
+struct QuoteTerms {
+    asset_id: String,
+    amount: u64,
+    base_price: u64,
+    fee: u64,
+    price: u64,
+    expiry: u64,
+    quote_id: String,
+    chain_id: u64,
+    payment_address: String,
+}
+
+/// Audit record of a purchase, written by `buy --receipt`
+#[derive(Debug, Serialize, Deserialize)]
+struct Receipt {
+    quote: QuoteTerms,
+    /// SHA-256 of the submitted compliance proof bytes
+    proof_hash: String,
+    public_values: String,
+    /// Server's X-Request-Id for the settlement call
+    request_id: Option<String>,
+    tx_hash: Option<String>,
+    settlement: SettlementResponse,
+    recorded_at: u64,
+}
+
+#[derive(Debug, Deserialize)]
+struct HealthResponse {
+    status: String,
+    chain_id: u64,
+    block_number: u64,
+}
+
+/// Metadata from `/api/v1/compliance/circuit/:asset`
+#[derive(Debug, Deserialize)]
+struct ComplianceCircuit {
+    circuit_id: String,
+    name: String,
+    verifier_address: String,
+    required_claims: Vec<String>,
+}
+
 #[derive(Debug, Deserialize)]
 struct AgentStatus {
     address: String,
@@ -133,52 +267,203 @@ struct Agent {
     client: Client,
     server: String,
     wallet_address: String,
+    signer: Option<LocalWallet>,
+    server_signer: Option<Address>,
+    /// Proof cache directory; `None` disables caching
+    proof_cache: Option<PathBuf>,
+    /// Tolerance over a fresh quote used as the default price limit
+    slippage_bps: u64,
+    /// Chain the server is expected to settle on
+    chain_id: u64,
+    /// Skip the health check before buying
+    skip_preflight: bool,
 }
 
 impl Agent {
-    fn new(server: &str) -> Self {
-        Self {
+    /// `signer` signs settlement requests; without one the agent can only dry-run
+    #[allow(clippy::too_many_arguments)]
+    fn new(
+        server: &str,
+        signer: Option<LocalWallet>,
+        server_signer: Option<Address>,
+        proof_cache: Option<PathBuf>,
+        slippage_bps: u64,
+        chain_id: u64,
+        skip_preflight: bool,
+        address: Option<Address>,
+    ) -> Result<Self> {
+        // Proofs bind to this address, so it must be the signer's when there is one
+        let address = match (&signer, address) {
+            (Some(wallet), Some(address)) if address != wallet.address() => anyhow::bail!(
+                "--address {} does not match AGENT_PRIVATE_KEY ({})",
+                to_checksum(&address, None),
+                to_checksum(&wallet.address(), None)
+            ),
+            (Some(wallet), _) => wallet.address(),
+            (None, Some(address)) => address,
+            // Mock wallet address for demo
+            (None, None) => "0x742d35Cc6634C0532925a3b844Bc9e7595f1Ab23".parse()?,
+        };
+        let wallet_address = to_checksum(&address, None);
+        
+        Ok(Self {
             client: Client::new(),
             server: server.to_string(),
-            // Mock wallet address for demo
-            wallet_address: "0x742d35Cc6634C0532925a3b844Bc9e7595f1Ab23".to_string(),
+            wallet_address,
+            signer,
+            server_signer,
+            proof_cache,
+            slippage_bps,
+            chain_id,
+            skip_preflight,
+        })
+    }
+    
+    /// Abort early unless the server is healthy and on the expected chain
+    async fn preflight(&self) -> Result<()> {
+        if self.skip_preflight {
+            return Ok(());
+        }
+        
+        let url = format!("{}/health", self.server);
+        let resp = self.client.get(&url).send().await
+            .with_context(|| format!("Server unreachable at {}", self.server))?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Server health check failed with {} - aborting purchase", resp.status());
         }
+        let health: HealthResponse = resp.json().await.context("Invalid /health response")?;
+        
+        if health.status == "degraded" {
+            anyhow::bail!("Server reports degraded health - aborting purchase");
+        }
+        if health.chain_id != self.chain_id {
+            anyhow::bail!(
+                "Server is on chain {}, expected {} - aborting purchase",
+                health.chain_id,
+                self.chain_id
+            );
+        }
+        
+        println!("[*] Preflight: server {} on chain {} (block {})", health.status, health.chain_id, health.block_number);
+        Ok(())
     }
     
     async fn list_assets(&self) -> Result<Vec<Asset>> {
         let url = format!("{}/api/v1/assets", self.server);
         let resp = self.client.get(&url).send().await?;
-        let assets: Vec<Asset> = resp.json().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Listing assets failed: {}", server_error(resp).await);
+        }
+        let assets: Vec<Asset> = resp.json().await.context("Invalid asset list response")?;
         Ok(assets)
     }
     
     async fn get_quote(&self, asset: &str, amount: u64) -> Result<Quote> {
         let url = format!("{}/api/v1/trade/quote/{}?amount={}", self.server, asset, amount);
         let resp = self.client.get(&url).send().await?;
-        let quote: Quote = resp.json().await?;
-        Ok(quote)
+        if !resp.status().is_success() {
+            anyhow::bail!("Fetching quote failed: {}", server_error(resp).await);
+        }
+        resp.json().await.context("Invalid quote response")
+    }
+    
+    async fn get_compliance_circuit(&self, asset: &str) -> Result<ComplianceCircuit> {
+        let url = format!("{}/api/v1/compliance/circuit/{}", self.server, asset);
+        let resp = self.client.get(&url).send().await?;
+        if !resp.status().is_success() {
+            anyhow::bail!("Fetching compliance circuit failed: {}", server_error(resp).await);
+        }
+        resp.json().await.context("Invalid compliance circuit response")
     }
     
     /// Core x402 flow: Get challenge, generate proof, execute settlement
-    async fn buy(&self, asset: &str, amount: u64, dry_run: bool) -> Result<SettlementResponse> {
+    async fn buy(
+        &self,
+        asset: &str,
+        amount: u64,
+        dry_run: bool,
+        max_price: Option<u64>,
+    ) -> Result<Receipt> {
+        self.preflight().await?;
+        self.buy_with_proofs(asset, amount, dry_run, max_price, &mut HashMap::new()).await
+    }
+    
+    /// x402 flow reusing compliance proofs already generated for a circuit
+    async fn buy_with_proofs(
+        &self,
+        asset: &str,
+        amount: u64,
+        dry_run: bool,
+        max_price: Option<u64>,
+        proofs: &mut HashMap<String, (String, String)>,
+    ) -> Result<Receipt> {
         println!("\n[*] Agent: Initiating x402 purchase flow");
         println!("    Asset: {}", asset);
         println!("    Amount: {}", amount);
         
+        // Step 0: Price limit, defaulting to a fresh quote plus slippage tolerance
+        let max_price = match max_price {
+            Some(limit) => limit,
+            None => {
+                let quote = self.get_quote(asset, amount).await?;
+                quote.total_price
+                    .checked_mul(self.slippage_bps)
+                    .and_then(|tolerance| quote.total_price.checked_add(tolerance / 10_000))
+                    .with_context(|| format!(
+                        "Max price overflows: quote of {} with {} bps slippage",
+                        quote.total_price,
+                        self.slippage_bps
+                    ))?
+            }
+        };
+        println!("    Max price: {} USDC", max_price as f64 / 1_000_000.0);
+        
         // Step 1: Initial probe (expecting 402)
         let url = format!("{}/api/v1/trade/buy/{}?amount={}", self.server, asset, amount);
         println!("\n[1] Sending initial request to {}", url);
         
-        let resp = self.client.get(&url).send().await?;
+        let resp = self.client
+            .get(&url)
+            .header(
+                reqwest::header::ACCEPT,
+                format!("application/x402-rwa+json; version={}", X402_VERSION),
+            )
+            .send()
+            .await?;
         
+        if resp.status() == reqwest::StatusCode::NOT_ACCEPTABLE {
+            anyhow::bail!(
+                "Server does not speak x402-RWA version {}: {}",
+                X402_VERSION,
+                server_error(resp).await
+            );
+        }
         if resp.status().as_u16() != 402 {
             anyhow::bail!("Expected 402 Payment Required, got {}", resp.status());
         }
         
         println!("[!] Received 402 Payment Required");
         
-        // Step 2: Parse x402 headers
-        let headers = resp.headers();
+        // Step 2: Parse the x402-RWA challenge and its X-402-* terms
+        let headers = resp.headers().clone();
+        // Servers predating version negotiation send no X-402-Version
+        if let Some(version) = headers.get("X-402-Version").and_then(|v| v.to_str().ok()) {
+            if version != X402_VERSION.to_string() {
+                anyhow::bail!("Server answered with x402-RWA version {}, expected {}", version, X402_VERSION);
+            }
+        }
+        let challenge = headers.get(reqwest::header::WWW_AUTHENTICATE)
+            .and_then(|v| v.to_str().ok())
+            .context("Missing WWW-Authenticate")
+            .and_then(parse_x402_challenge)?;
+        let challenge_param = |key: &str| {
+            challenge.get(key)
+                .map(String::as_str)
+                .with_context(|| format!("WWW-Authenticate challenge has no {}", key))
+        };
+        let compliance_circuit = challenge_param("circuit")?;
+        let quote_id = challenge_param("quote_id")?;
+        let submit_url = format!("{}{}", self.server, challenge_param("submit")?);
         let asset_id = headers.get("X-402-Asset-ID")
             .and_then(|v| v.to_str().ok())
             .context("Missing X-402-Asset-ID")?;
@@ -186,9 +471,14 @@ impl Agent {
             .and_then(|v| v.to_str().ok())
             .and_then(|v| v.parse::<u64>().ok())
             .context("Missing X-402-Price")?;
-        let compliance_circuit = headers.get("X-402-Compliance-Circuit")
+        let base_price = headers.get("X-402-Base-Price")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u64>().ok())
+            .context("Missing X-402-Base-Price")?;
+        let fee = headers.get("X-402-Fee")
             .and_then(|v| v.to_str().ok())
-            .context("Missing X-402-Compliance-Circuit")?;
+            .and_then(|v| v.parse::<u64>().ok())
+            .context("Missing X-402-Fee")?;
         let payment_address = headers.get("X-402-Payment-Address")
             .and_then(|v| v.to_str().ok())
             .context("Missing X-402-Payment-Address")?;
@@ -196,18 +486,74 @@ impl Agent {
             .and_then(|v| v.to_str().ok())
             .and_then(|v| v.parse::<u64>().ok())
             .context("Missing X-402-Expiry")?;
-        let quote_id = headers.get("X-402-Quote-ID")
+        let chain_id = headers.get("X-402-Chain-ID")
             .and_then(|v| v.to_str().ok())
-            .context("Missing X-402-Quote-ID")?;
+            .and_then(|v| v.parse::<u64>().ok())
+            .context("Missing X-402-Chain-ID")?;
         
         println!("\n[2] Parsed x402 challenge:");
         println!("    Asset ID: {}", asset_id);
+        println!("    Base Price: {} USDC", base_price as f64 / 1_000_000.0);
+        println!("    Fee: {} USDC", fee as f64 / 1_000_000.0);
         println!("    Price: {} USDC", price as f64 / 1_000_000.0);
-        println!("    Compliance Circuit: {}...", &compliance_circuit[..16]);
+        
+        if base_price.checked_add(fee) != Some(price) {
+            anyhow::bail!(
+                "Inconsistent challenge: base price {} + fee {} != total {}",
+                base_price,
+                fee,
+                price
+            );
+        }
+        
+        let quote = QuoteTerms {
+            asset_id: asset_id.to_string(),
+            amount,
+            base_price,
+            fee,
+            price,
+            expiry,
+            quote_id: quote_id.to_string(),
+            chain_id,
+            payment_address: payment_address.to_string(),
+        };
+        let body: X402Response = resp.json().await.context("Invalid x402 challenge body")?;
+        check_challenge_body(&body, &quote, compliance_circuit)?;
+        println!("    Compliance Circuit: {}...", preview(compliance_circuit, 16));
         println!("    Payment Address: {}", payment_address);
         println!("    Expiry: {}", expiry);
         println!("    Quote ID: {}", quote_id);
         
+        // Authenticate the quote before trusting its price and payment address
+        match self.server_signer {
+            Some(expected) => {
+                let signature = headers.get("X-402-Signature")
+                    .and_then(|v| v.to_str().ok())
+                    .context("Missing X-402-Signature")?;
+                let digest = quote_digest(
+                    asset_id,
+                    amount,
+                    price,
+                    payment_address.parse().context("Invalid X-402-Payment-Address")?,
+                    expiry,
+                    quote_id,
+                    chain_id,
+                );
+                verify_quote_signature(&digest, signature, expected)?;
+                println!("    Signature: verified ({:?})", expected);
+            }
+            None => println!("    Signature: not verified (no --server-signer configured)"),
+        }
+        
+        // Abort on slippage before spending time on a proof
+        if price > max_price {
+            anyhow::bail!(
+                "Challenge price {} USDC exceeds max price {} USDC - aborting purchase",
+                price as f64 / 1_000_000.0,
+                max_price as f64 / 1_000_000.0
+            );
+        }
+        
         // Step 3: Decision engine (risk assessment)
         let risk_acceptable = self.evaluate_risk(asset_id, price, amount, expiry);
         if !risk_acceptable {
@@ -215,52 +561,89 @@ impl Agent {
         }
         println!("\n[3] Risk assessment: PASSED");
         
+        // Make sure the proof would prove the right claims to the right verifier
+        let circuit = self.get_compliance_circuit(asset_id).await?;
+        check_compliance_circuit(&circuit, compliance_circuit, payment_address)?;
+        println!("    Circuit: {} ({})", circuit.name, circuit.required_claims.join(", "));
+        
         // Step 4: Generate ZK compliance proof
         println!("\n[4] Generating ZK compliance proof...");
-        let (proof, public_values) = self.generate_compliance_proof(compliance_circuit)?;
+        let (proof, public_values) = match proofs.get(compliance_circuit) {
+            Some(cached) => {
+                println!("    Reusing proof for circuit {}...", preview(compliance_circuit, 16));
+                cached.clone()
+            }
+            None => {
+                let generated = self.compliance_proof(compliance_circuit)?;
+                proofs.insert(compliance_circuit.to_string(), generated.clone());
+                generated
+            }
+        };
         println!("    Proof generated: {} bytes", proof.len() / 2);
         
+        let proof_hash = format!(
+            "0x{:x}",
+            Sha256::digest(hex::decode(proof.trim_start_matches("0x"))?)
+        );
+        
         // Step 5: Prepare payment (in production: sign USDC transfer)
         println!("\n[5] Preparing payment transaction...");
         // In production: create and sign the USDC approval/transfer
         
         if dry_run {
             println!("\n[DRY RUN] Would submit:");
-            println!("    Proof: {}...", &proof[..32]);
-            println!("    Public values: {}...", &public_values[..32]);
+            println!("    Proof: {}...", preview(&proof, 32));
+            println!("    Public values: {}...", preview(&public_values, 32));
             
-            return Ok(SettlementResponse {
-                status: "dry_run".to_string(),
+            let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+            return Ok(Receipt {
+                quote,
+                proof_hash,
+                public_values,
+                request_id: None,
                 tx_hash: None,
-                asset_delivered: asset.to_string(),
-                amount,
-                settlement_id: "DRY_RUN".to_string(),
-                timestamp: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+                settlement: SettlementResponse {
+                    status: "dry_run".to_string(),
+                    tx_hash: None,
+                    asset_delivered: asset.to_string(),
+                    amount,
+                    settlement_id: "DRY_RUN".to_string(),
+                    timestamp: now,
+                },
+                recorded_at: now,
             });
         }
         
         // Step 6: Execute settlement
         println!("\n[6] Executing atomic settlement...");
         
+        let request_signature = self
+            .sign_settlement(asset, amount, quote_id, &proof, &public_values)
+            .await?;
+        
         let settlement_request = SettlementRequest {
             asset: asset.to_string(),
             amount,
             quote_id: quote_id.to_string(),
             compliance_proof: proof,
-            public_values,
+            public_values: public_values.clone(),
+            request_signature,
         };
         
         let resp = self.client
-            .post(&url)
+            .post(&submit_url)
             .json(&settlement_request)
             .send()
             .await?;
         
         if !resp.status().is_success() {
-            let error_text = resp.text().await?;
-            anyhow::bail!("Settlement failed: {}", error_text);
+            anyhow::bail!("Settlement failed: {}", server_error(resp).await);
         }
         
+        let request_id = resp.headers()
+            .get("X-Request-Id")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string);
         let settlement: SettlementResponse = resp.json().await?;
         
         println!("\n[$] SETTLEMENT COMPLETE");
@@ -269,7 +652,39 @@ impl Agent {
         println!("    Asset Delivered: {}", settlement.asset_delivered);
         println!("    Amount: {}", settlement.amount);
         
-        Ok(settlement)
+        Ok(Receipt {
+            quote,
+            proof_hash,
+            public_values,
+            request_id,
+            tx_hash: settlement.tx_hash.clone(),
+            settlement,
+            recorded_at: SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs(),
+        })
+    }
+    
+    /// Buy every item of a basket, sharing compliance proofs across items
+    ///
+    /// Returns the outcome of each item in basket order.
+    async fn buy_portfolio(
+        &self,
+        basket: &[BasketItem],
+        dry_run: bool,
+    ) -> Vec<Result<Receipt>> {
+        let mut proofs = HashMap::new();
+        let mut outcomes = Vec::with_capacity(basket.len());
+        
+        for item in basket {
+            let outcome = self
+                .buy_with_proofs(&item.asset, item.amount, dry_run, None, &mut proofs)
+                .await;
+            if let Err(e) = &outcome {
+                println!("\n[!] Purchase of {} failed: {:#}", item.asset, e);
+            }
+            outcomes.push(outcome);
+        }
+        
+        outcomes
     }
     
     /// Simple risk evaluation (expand in production)
@@ -282,7 +697,7 @@ impl Agent {
 
         // Check price is reasonable (T-Bills trade near par ~$0.98)
         let price_per_unit = (total_price as f64 / amount as f64) / 1_000_000.0;
-        if price_per_unit < 0.90 || price_per_unit > 1.10 {
+        if !(0.90..=1.10).contains(&price_per_unit) {
             tracing::warn!("Price outside acceptable range: ${:.4}/unit", price_per_unit);
             return false;
         }
@@ -297,6 +712,65 @@ impl Agent {
         true
     }
     
+    /// Compliance proof for a circuit, from the cache while it is still valid
+    fn compliance_proof(&self, circuit_id: &str) -> Result<(String, String)> {
+        let Some(cache_dir) = &self.proof_cache else {
+            return self.generate_compliance_proof(circuit_id);
+        };
+        
+        let path = self.proof_cache_path(cache_dir, circuit_id);
+        
+        let now = SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs();
+        if let Some(cached) = std::fs::read_to_string(&path)
+            .ok()
+            .and_then(|content| serde_json::from_str::<CachedProof>(&content).ok())
+        {
+            if commits_current_time(&cached.public_values) {
+                println!("    Cached proof commits its proving time, regenerating");
+            } else if cached.valid_until > now + PROOF_REFRESH_MARGIN_SECS {
+                println!("    Using cached proof (valid until {})", cached.valid_until);
+                return Ok((cached.proof, cached.public_values));
+            }
+            println!("    Cached proof expires at {}, regenerating", cached.valid_until);
+        }
+        
+        let (proof, public_values) = self.generate_compliance_proof(circuit_id)?;
+        
+        // A committed proving time goes stale within the server's tolerance
+        if commits_current_time(&public_values) {
+            return Ok((proof, public_values));
+        }
+        
+        // valid_until is the second ABI word of the public values
+        let values = hex::decode(public_values.trim_start_matches("0x"))?;
+        let valid_until = values
+            .get(56..64)
+            .map(|b| u64::from_be_bytes(b.try_into().unwrap()))
+            .context("Public values missing valid_until")?;
+        
+        let cached = CachedProof {
+            circuit_id: circuit_id.to_string(),
+            agent_address: self.wallet_address.clone(),
+            proof: proof.clone(),
+            public_values: public_values.clone(),
+            valid_until,
+        };
+        std::fs::create_dir_all(cache_dir)
+            .with_context(|| format!("Cannot create proof cache {}", cache_dir.display()))?;
+        std::fs::write(&path, serde_json::to_string_pretty(&cached)?)
+            .with_context(|| format!("Cannot write {}", path.display()))?;
+        
+        Ok((proof, public_values))
+    }
+    
+    /// Cache file for a circuit, keyed by (circuit_id, agent_address)
+    fn proof_cache_path(&self, cache_dir: &std::path::Path, circuit_id: &str) -> PathBuf {
+        let mut hasher = Sha256::new();
+        hasher.update(circuit_id.as_bytes());
+        hasher.update(self.wallet_address.to_lowercase().as_bytes());
+        cache_dir.join(format!("{:x}.json", hasher.finalize()))
+    }
+    
     /// Generate SP1 ZK proof for compliance
     fn generate_compliance_proof(&self, circuit_id: &str) -> Result<(String, String)> {
         // In production:
@@ -310,7 +784,7 @@ impl Agent {
         // Mock proof data
         let mut hasher = Sha256::new();
         hasher.update(circuit_id.as_bytes());
-        hasher.update(&now.to_le_bytes());
+        hasher.update(now.to_le_bytes());
         hasher.update(self.wallet_address.as_bytes());
         let proof_hash = hasher.finalize();
         
@@ -330,20 +804,258 @@ impl Agent {
         public_values.extend_from_slice(&[0u8; 24]);
         public_values.extend_from_slice(&valid_until.to_be_bytes());
         
-        // Add jurisdiction hash (32 bytes)
+        // Add jurisdiction hash (32 bytes), derived as in the circuit
         let mut hasher = Sha256::new();
         hasher.update(b"US");
+        hasher.update(b"X402_JURISDICTION_V1");
         public_values.extend_from_slice(&hasher.finalize());
         
         Ok((format!("0x{}", mock_proof), format!("0x{}", hex::encode(&public_values))))
     }
     
+    /// Sign a settlement request (EIP-191) so the server can bind it to the proven agent
+    async fn sign_settlement(
+        &self,
+        asset: &str,
+        amount: u64,
+        quote_id: &str,
+        proof: &str,
+        public_values: &str,
+    ) -> Result<String> {
+        let signer = self
+            .signer
+            .as_ref()
+            .context("AGENT_PRIVATE_KEY is required to sign settlement requests")?;
+        
+        let proof = hex::decode(proof.trim_start_matches("0x"))?;
+        let public_values = hex::decode(public_values.trim_start_matches("0x"))?;
+        let digest = settlement_digest(asset, amount, quote_id, &proof, &public_values);
+        
+        let signature = signer.sign_message(&digest[..]).await?;
+        Ok(format!("0x{}", hex::encode(signature.to_vec())))
+    }
+    
     async fn get_status(&self, address: &str) -> Result<AgentStatus> {
         let url = format!("{}/api/v1/agent/{}/status", self.server, address);
         let resp = self.client.get(&url).send().await?;
-        let status: AgentStatus = resp.json().await?;
-        Ok(status)
+        if !resp.status().is_success() {
+            anyhow::bail!("Fetching agent status failed: {}", server_error(resp).await);
+        }
+        resp.json().await.context("Invalid agent status response")
+    }
+}
+
+/// Parameters of a `WWW-Authenticate: x402-RWA k="v", ...` challenge
+fn parse_x402_challenge(header: &str) -> Result<HashMap<String, String>> {
+    let params = header
+        .strip_prefix("x402-RWA ")
+        .with_context(|| format!("Not an x402-RWA challenge: {}", header))?;
+    
+    let mut parsed = HashMap::new();
+    let mut chars = params.chars().peekable();
+    loop {
+        while chars.next_if(|c| *c == ',' || c.is_whitespace()).is_some() {}
+        if chars.peek().is_none() {
+            break;
+        }
+        
+        let key: String = std::iter::from_fn(|| chars.next_if(|c| *c != '=')).collect();
+        anyhow::ensure!(chars.next() == Some('='), "Malformed challenge parameter {}", key);
+        
+        let mut value = String::new();
+        if chars.next_if_eq(&'"').is_some() {
+            // Quoted-string: backslash escapes the next character
+            loop {
+                match chars.next() {
+                    Some('"') => break,
+                    Some('\\') => value.extend(chars.next()),
+                    Some(c) => value.push(c),
+                    None => anyhow::bail!("Unterminated value for {}", key),
+                }
+            }
+        } else {
+            value.extend(std::iter::from_fn(|| chars.next_if(|c| *c != ',')));
+        }
+        parsed.insert(key.trim().to_string(), value.trim().to_string());
+    }
+    Ok(parsed)
+}
+
+/// Reject a challenge whose body disagrees with the headers it was parsed from
+fn check_challenge_body(
+    body: &X402Response,
+    quote: &QuoteTerms,
+    compliance_circuit: &str,
+) -> Result<()> {
+    let checks = [
+        ("protocol", body.protocol == format!("x402-RWA/{}.0", X402_VERSION)),
+        ("asset", body.asset == quote.asset_id),
+        ("amount", body.amount == quote.amount),
+        ("base_price", body.base_price == quote.base_price),
+        ("fee", body.fee == quote.fee),
+        ("total_price", body.total_price == quote.price),
+        ("currency", body.currency == "USDC"),
+        ("expiry", body.expiry == quote.expiry),
+        ("quote_id", body.quote_id == quote.quote_id),
+        ("compliance_circuit", body.compliance_circuit.eq_ignore_ascii_case(compliance_circuit)),
+        ("payment_address", body.payment_address.eq_ignore_ascii_case(&quote.payment_address)),
+        ("chain_id", body.chain_id == quote.chain_id),
+    ];
+    let mismatched: Vec<&str> = checks
+        .iter()
+        .filter(|(_, agrees)| !agrees)
+        .map(|(field, _)| *field)
+        .collect();
+    if !mismatched.is_empty() {
+        anyhow::bail!(
+            "x402 challenge body disagrees with its headers on {} - aborting purchase",
+            mismatched.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Refuse to prove for circuit metadata that disagrees with the challenge or
+/// requires claims this agent cannot satisfy
+fn check_compliance_circuit(
+    circuit: &ComplianceCircuit,
+    challenge_circuit: &str,
+    payment_address: &str,
+) -> Result<()> {
+    if !circuit.circuit_id.eq_ignore_ascii_case(challenge_circuit) {
+        anyhow::bail!(
+            "Compliance circuit {} does not match the challenge's {} - aborting purchase",
+            circuit.circuit_id,
+            challenge_circuit
+        );
+    }
+    
+    let verifier: Address = circuit.verifier_address.parse()
+        .context("Invalid verifier_address in circuit metadata")?;
+    let expected: Address = payment_address.parse()
+        .context("Invalid X-402-Payment-Address")?;
+    if verifier != expected {
+        anyhow::bail!(
+            "Circuit verifier {:?} is not the payment address {:?} - aborting purchase",
+            verifier,
+            expected
+        );
+    }
+    
+    let missing: Vec<&str> = circuit.required_claims
+        .iter()
+        .map(String::as_str)
+        .filter(|claim| !SUPPORTED_CLAIMS.contains(claim))
+        .collect();
+    if !missing.is_empty() {
+        anyhow::bail!(
+            "Circuit requires claims this agent cannot prove: {} - aborting purchase",
+            missing.join(", ")
+        );
+    }
+    Ok(())
+}
+
+/// Whether public values carry a fourth word, the circuit's proving time
+fn commits_current_time(public_values: &str) -> bool {
+    hex::decode(public_values.trim_start_matches("0x"))
+        .is_ok_and(|values| values.len() > PUBLIC_VALUES_LEN)
+}
+
+/// First `len` characters of an untrusted value, for display
+fn preview(value: &str, len: usize) -> String {
+    value.chars().take(len).collect()
+}
+
+/// Server error body: `{error, code}`
+#[derive(Debug, Deserialize)]
+struct ErrorResponse {
+    error: String,
+    code: u16,
+}
+
+/// Describe a failed response, preferring the server's structured error
+async fn server_error(resp: reqwest::Response) -> String {
+    let status = resp.status();
+    let body = resp.text().await.unwrap_or_default();
+    match serde_json::from_str::<ErrorResponse>(&body) {
+        Ok(err) => format!("{} (code {})", err.error, err.code),
+        Err(_) => format!("{} {}", status, body),
+    }
+}
+
+/// Digest the server expects to be signed for a settlement
+///
+/// Equivalent to `keccak256(abi.encode(keccak256(asset), amount,
+/// keccak256(quote_id), keccak256(proof), keccak256(public_values)))`.
+fn settlement_digest(
+    asset: &str,
+    amount: u64,
+    quote_id: &str,
+    proof: &[u8],
+    public_values: &[u8],
+) -> [u8; 32] {
+    let mut amount_word = [0u8; 32];
+    U256::from(amount).to_big_endian(&mut amount_word);
+    
+    let mut encoded = Vec::with_capacity(5 * 32);
+    encoded.extend_from_slice(&keccak256(asset.as_bytes()));
+    encoded.extend_from_slice(&amount_word);
+    encoded.extend_from_slice(&keccak256(quote_id.as_bytes()));
+    encoded.extend_from_slice(&keccak256(proof));
+    encoded.extend_from_slice(&keccak256(public_values));
+    keccak256(encoded)
+}
+
+/// Digest the server signs over an x402 quote
+///
+/// Equivalent to `keccak256(abi.encode(keccak256(asset), amount, totalPrice,
+/// paymentAddress, expiry, keccak256(quote_id), chainId))`.
+fn quote_digest(
+    asset: &str,
+    amount: u64,
+    total_price: u64,
+    payment_address: Address,
+    expiry: u64,
+    quote_id: &str,
+    chain_id: u64,
+) -> [u8; 32] {
+    let word = |value: u64| {
+        let mut word = [0u8; 32];
+        U256::from(value).to_big_endian(&mut word);
+        word
+    };
+    
+    let mut encoded = Vec::with_capacity(7 * 32);
+    encoded.extend_from_slice(&keccak256(asset.as_bytes()));
+    encoded.extend_from_slice(&word(amount));
+    encoded.extend_from_slice(&word(total_price));
+    encoded.extend_from_slice(&[0u8; 12]);
+    encoded.extend_from_slice(payment_address.as_bytes());
+    encoded.extend_from_slice(&word(expiry));
+    encoded.extend_from_slice(&keccak256(quote_id.as_bytes()));
+    encoded.extend_from_slice(&word(chain_id));
+    keccak256(encoded)
+}
+
+/// Check an X-402-Signature was produced by the expected server key
+fn verify_quote_signature(digest: &[u8; 32], signature: &str, expected: Address) -> Result<()> {
+    let signature: Signature = signature
+        .trim_start_matches("0x")
+        .parse()
+        .context("Malformed X-402-Signature")?;
+    let signer = signature
+        .recover(&digest[..])
+        .context("Could not recover quote signer")?;
+    
+    if signer != expected {
+        anyhow::bail!(
+            "Quote signature mismatch: signed by {:?}, expected {:?} - aborting purchase",
+            signer,
+            expected
+        );
     }
+    Ok(())
 }
 
 #[tokio::main]
@@ -353,12 +1065,39 @@ async fn main() -> Result<()> {
     dotenvy::dotenv().ok();
     
     let cli = Cli::parse();
-    let agent = Agent::new(&cli.server);
+    let no_cache = matches!(
+        cli.command,
+        Commands::Buy { no_cache: true, .. } | Commands::Portfolio { no_cache: true, .. }
+    );
+    let proof_cache = (!no_cache).then_some(cli.proof_cache);
+    let no_preflight = matches!(
+        cli.command,
+        Commands::Buy { no_preflight: true, .. } | Commands::Portfolio { no_preflight: true, .. }
+    );
+    // Settlement requests are signed with AGENT_PRIVATE_KEY when provided
+    let signer = match std::env::var("AGENT_PRIVATE_KEY") {
+        Ok(key) => Some(key.parse::<LocalWallet>().context("Invalid AGENT_PRIVATE_KEY")?),
+        Err(_) => None,
+    };
+    let agent = Agent::new(
+        &cli.server,
+        signer,
+        cli.server_signer,
+        proof_cache,
+        cli.slippage_bps,
+        cli.chain_id,
+        no_preflight,
+        cli.address,
+    )?;
     
     match cli.command {
         Commands::List => {
             println!("Fetching available assets...\n");
             let assets = agent.list_assets().await?;
+            if assets.is_empty() {
+                println!("No assets available");
+                return Ok(());
+            }
             
             println!("{:<12} {:<30} {:<10} {:<12}", "ID", "Name", "Price", "Status");
             println!("{}", "-".repeat(70));
@@ -383,20 +1122,69 @@ async fn main() -> Result<()> {
             println!("  Asset:         {}", quote.asset_id);
             println!("  Amount:        {}", quote.amount);
             println!("  Price/Unit:    ${:.4}", price_per_unit);
-            println!("  Fee:           ${:.4} (0.05%)", fee);
+            let fee_pct = if quote.total_price > quote.fee {
+                quote.fee as f64 * 100.0 / (quote.total_price - quote.fee) as f64
+            } else {
+                0.0
+            };
+            println!("  Fee:           ${:.4} ({:.2}%)", fee, fee_pct);
             println!("  Total:         ${:.4}", total);
             println!("  Quote ID:      {}", quote.quote_id);
-            println!("  Valid Until:   {}", quote.expiry);
+            println!("  Valid Until:   {} ({}s remaining)", quote.expiry, quote.expires_in_seconds);
+        }
+        
+        Commands::Buy { asset, amount, dry_run, max_price, receipt, .. } => {
+            if dry_run {
+                println!("=== DRY RUN MODE ===\n");
+            }
+            let max_price = max_price.map(|usdc| (usdc * 1_000_000.0).round() as u64);
+            let settled = agent.buy(&asset, amount, dry_run, max_price).await?;
+            if let Some(path) = receipt {
+                std::fs::write(&path, serde_json::to_string_pretty(&settled)?)
+                    .with_context(|| format!("Failed to write receipt {}", path.display()))?;
+                println!("\n📄 Receipt written to {}", path.display());
+            }
         }
         
-        Commands::Buy { asset, amount, dry_run } => {
+        Commands::Portfolio { file, dry_run, .. } => {
             if dry_run {
                 println!("=== DRY RUN MODE ===\n");
             }
-            agent.buy(&asset, amount, dry_run).await?;
+            let basket: Vec<BasketItem> = serde_json::from_str(
+                &std::fs::read_to_string(&file).with_context(|| format!("Cannot read {}", file))?,
+            )
+            .with_context(|| format!("Invalid basket file {}", file))?;
+            
+            agent.preflight().await?;
+            let outcomes = agent.buy_portfolio(&basket, dry_run).await;
+            
+            println!("\nPortfolio Summary:");
+            println!("{:<12} {:<10} {:<12} Details", "Asset", "Amount", "Result");
+            println!("{}", "-".repeat(70));
+            let mut failed = 0;
+            for (item, outcome) in basket.iter().zip(&outcomes) {
+                match outcome {
+                    Ok(receipt) => println!(
+                        "{:<12} {:<10} {:<12} {}",
+                        item.asset,
+                        item.amount,
+                        receipt.settlement.status,
+                        receipt.tx_hash.as_deref().unwrap_or("N/A")
+                    ),
+                    Err(e) => {
+                        failed += 1;
+                        println!("{:<12} {:<10} {:<12} {:#}", item.asset, item.amount, "failed", e);
+                    }
+                }
+            }
+            
+            if failed > 0 {
+                anyhow::bail!("{} of {} purchases failed", failed, outcomes.len());
+            }
         }
         
         Commands::Status { address } => {
+            let address = address.unwrap_or_else(|| agent.wallet_address.clone());
             let status = agent.get_status(&address).await?;
             
             println!("Agent Status:");
@@ -412,3 +1200,516 @@ async fn main() -> Result<()> {
     
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::{Arc, Mutex};
+
+    const CIRCUIT: &str = "0xDd2ffa97F680032332EA4905586e2366584Ae0be";
+    const CLEARINGHOUSE: &str = "0xb315C8F827e3834bB931986F177cb1fb6D20415D";
+    /// Anvil/Hardhat account #1
+    const AGENT_KEY: &str = "0x59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    fn quote() -> QuoteTerms {
+        QuoteTerms {
+            asset_id: "TBILL-26".to_string(),
+            amount: 5,
+            base_price: 4_900_000,
+            fee: 2_450,
+            price: 4_902_450,
+            expiry: 1_700_000_100,
+            quote_id: "0xfeed".to_string(),
+            chain_id: 8453,
+            payment_address: CLEARINGHOUSE.to_string(),
+        }
+    }
+
+    fn body() -> X402Response {
+        X402Response {
+            protocol: "x402-RWA/1.0".to_string(),
+            asset: "TBILL-26".to_string(),
+            amount: 5,
+            base_price: 4_900_000,
+            fee: 2_450,
+            total_price: 4_902_450,
+            currency: "USDC".to_string(),
+            expiry: 1_700_000_100,
+            quote_id: "0xfeed".to_string(),
+            compliance_circuit: CIRCUIT.to_lowercase(),
+            payment_address: CLEARINGHOUSE.to_lowercase(),
+            chain_id: 8453,
+        }
+    }
+
+    // The server pins the same vectors in services/auth.rs, so the two
+    // copies of each digest cannot drift apart
+    #[test]
+    fn settlement_digest_matches_pinned_vector() {
+        let digest = settlement_digest("TBILL-26", 5, "0xfeed", &[1, 2, 3], &[4, 5, 6]);
+        assert_eq!(
+            hex::encode(digest),
+            "d03c1eea6a7298df75ca768ae93526e79957e573c536c78e919b93f149698579"
+        );
+    }
+
+    #[test]
+    fn quote_digest_matches_pinned_vector() {
+        let digest = quote_digest("TBILL-26", 5, 4_902_450, CLEARINGHOUSE.parse().unwrap(), 1_700_000_100, "0xfeed", 8453);
+        assert_eq!(
+            hex::encode(digest),
+            "56c34f66e4cfa4d62692a3df92ea2368b706eb8398b495b9e8c7e6e3b0fff6f7"
+        );
+    }
+
+    #[test]
+    fn quote_signature_from_the_server_signer_passes_and_forgery_fails() {
+        // Anvil/Hardhat accounts #0 (server relay) and #1 (forger)
+        let server: LocalWallet = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80".parse().unwrap();
+        let forger: LocalWallet = "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d".parse().unwrap();
+        let digest = quote_digest("TBILL-26", 5, 4_902_450, CLEARINGHOUSE.parse().unwrap(), 1_700_000_100, "0xfeed", 8453);
+        let sign = |wallet: &LocalWallet| {
+            let signature = wallet.sign_hash(ethers::utils::hash_message(digest)).unwrap();
+            format!("0x{}", hex::encode(signature.to_vec()))
+        };
+
+        verify_quote_signature(&digest, &sign(&server), server.address()).unwrap();
+        assert!(verify_quote_signature(&digest, &sign(&forger), server.address()).is_err());
+    }
+
+    #[test]
+    fn challenge_body_matching_headers_passes() {
+        check_challenge_body(&body(), &quote(), CIRCUIT).unwrap();
+    }
+
+    #[test]
+    fn challenge_body_disagreeing_with_headers_is_rejected() {
+        let mut tampered = body();
+        tampered.fee = 1;
+        tampered.payment_address = "0x000000000000000000000000000000000000dEaD".to_string();
+        let err = check_challenge_body(&tampered, &quote(), CIRCUIT).unwrap_err();
+        assert!(err.to_string().contains("fee, payment_address"), "{}", err);
+    }
+
+    #[test]
+    fn parses_the_servers_www_authenticate_header() {
+        // As built by the server's www_authenticate
+        let header = "x402-RWA realm=\"402-clearinghouse\", circuit=\"0xDd2ffa97F680032332EA4905586e2366584Ae0be\", \
+                      quote_id=\"fe\\\"ed\", submit=\"/api/v1/trade/buy/TBILL-26\", verify=\"/api/v1/verify\"";
+        let params = parse_x402_challenge(header).unwrap();
+        assert_eq!(params["realm"], "402-clearinghouse");
+        assert_eq!(params["circuit"], CIRCUIT);
+        assert_eq!(params["quote_id"], "fe\"ed");
+        assert_eq!(params["submit"], "/api/v1/trade/buy/TBILL-26");
+        assert_eq!(params["verify"], "/api/v1/verify");
+    }
+
+    #[test]
+    fn malformed_challenges_are_rejected() {
+        for header in ["Bearer realm=\"x\"", "x402-RWA circuit", "x402-RWA circuit=\"0xDd2"] {
+            assert!(parse_x402_challenge(header).is_err(), "{}", header);
+        }
+        assert_eq!(parse_x402_challenge("x402-RWA realm=plain, a=b").unwrap()["realm"], "plain");
+    }
+
+    fn circuit(circuit_id: &str, verifier_address: &str, claims: &[&str]) -> ComplianceCircuit {
+        ComplianceCircuit {
+            circuit_id: circuit_id.to_string(),
+            name: "Accredited investor".to_string(),
+            verifier_address: verifier_address.to_string(),
+            required_claims: claims.iter().map(|claim| claim.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn circuit_metadata_matching_the_challenge_passes() {
+        let metadata = circuit(&CIRCUIT.to_lowercase(), &CLEARINGHOUSE.to_lowercase(), &["kyc_verified"]);
+        check_compliance_circuit(&metadata, CIRCUIT, CLEARINGHOUSE).unwrap();
+    }
+
+    #[test]
+    fn circuit_metadata_disagreeing_with_the_challenge_is_rejected() {
+        let other = "0x000000000000000000000000000000000000dEaD";
+        for metadata in [
+            circuit(other, CLEARINGHOUSE, &[]),
+            circuit(CIRCUIT, other, &[]),
+            circuit(CIRCUIT, "not an address", &[]),
+            circuit(CIRCUIT, CLEARINGHOUSE, &["kyc_verified", "resident_of_mars"]),
+        ] {
+            let result = check_compliance_circuit(&metadata, CIRCUIT, CLEARINGHOUSE);
+            assert!(result.is_err(), "{} / {}", metadata.circuit_id, metadata.verifier_address);
+        }
+    }
+
+    #[test]
+    fn preview_never_splits_a_character() {
+        assert_eq!(preview("0xab", 16), "0xab");
+        assert_eq!(preview("\u{e9}\u{e9}\u{e9}", 2), "\u{e9}\u{e9}");
+    }
+
+    /// Agent for `server` caching proofs in a fresh directory named after the test
+    fn agent_at(server: &str, name: &str) -> (Agent, PathBuf) {
+        let dir = std::env::temp_dir().join(format!("x402-proofs-{}-{}", name, std::process::id()));
+        let _ = std::fs::remove_dir_all(&dir);
+        let agent = Agent::new(server, None, None, Some(dir.clone()), 0, 8453, false, None).unwrap();
+        (agent, dir)
+    }
+
+    fn caching_agent(name: &str) -> (Agent, PathBuf) {
+        agent_at("http://localhost", name)
+    }
+
+    fn seed_cache(agent: &Agent, dir: &std::path::Path, valid_until: u64, words: usize) {
+        let cached = CachedProof {
+            circuit_id: CIRCUIT.to_string(),
+            agent_address: agent.wallet_address.clone(),
+            proof: "0xcached".to_string(),
+            public_values: format!("0x{}", "00".repeat(words * 32)),
+            valid_until,
+        };
+        std::fs::create_dir_all(dir).unwrap();
+        std::fs::write(agent.proof_cache_path(dir, CIRCUIT), serde_json::to_string(&cached).unwrap()).unwrap();
+    }
+
+    fn now() -> u64 {
+        SystemTime::now().duration_since(UNIX_EPOCH).unwrap().as_secs()
+    }
+
+    #[test]
+    fn cached_proof_within_validity_is_reused() {
+        let (agent, dir) = caching_agent("reuse");
+        seed_cache(&agent, &dir, now() + 7 * 24 * 60 * 60, 3);
+
+        let (proof, _) = agent.compliance_proof(CIRCUIT).unwrap();
+        assert_eq!(proof, "0xcached");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cached_proof_near_expiry_is_regenerated() {
+        let (agent, dir) = caching_agent("expired");
+        seed_cache(&agent, &dir, now() + 60, 3);
+
+        let (proof, _) = agent.compliance_proof(CIRCUIT).unwrap();
+        assert_ne!(proof, "0xcached");
+        let (again, _) = agent.compliance_proof(CIRCUIT).unwrap();
+        assert_eq!(again, proof, "the fresh proof is cached");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn cached_proof_with_committed_time_is_not_reused() {
+        let (agent, dir) = caching_agent("timed");
+        seed_cache(&agent, &dir, now() + 7 * 24 * 60 * 60, 4);
+
+        let (proof, _) = agent.compliance_proof(CIRCUIT).unwrap();
+        assert_ne!(proof, "0xcached");
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    /// Status, extra headers and JSON body of a stub server reply
+    type Reply = (u16, Vec<(&'static str, String)>, serde_json::Value);
+
+    /// Requests a stub server received, as "METHOD /path?query" and body
+    type Seen = Arc<Mutex<Vec<(String, String)>>>;
+
+    /// Local HTTP server answering each "METHOD /path?query" with `route`
+    async fn stub_server(route: impl Fn(&str) -> Reply + Send + Sync + 'static) -> (String, Seen) {
+        use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let server = format!("http://{}", listener.local_addr().unwrap());
+        let seen = Seen::default();
+        let (route, log) = (Arc::new(route), seen.clone());
+        tokio::spawn(async move {
+            loop {
+                let (stream, _) = listener.accept().await.unwrap();
+                let (route, log) = (route.clone(), log.clone());
+                tokio::spawn(async move {
+                    let mut stream = BufReader::new(stream);
+                    let mut request_line = String::new();
+                    stream.read_line(&mut request_line).await.unwrap();
+                    let mut content_length = 0;
+                    loop {
+                        let mut line = String::new();
+                        stream.read_line(&mut line).await.unwrap();
+                        match line.split_once(':') {
+                            Some((name, value)) if name.eq_ignore_ascii_case("content-length") => {
+                                content_length = value.trim().parse().unwrap();
+                            }
+                            _ if line.trim().is_empty() => break,
+                            _ => {}
+                        }
+                    }
+                    let mut body = vec![0; content_length];
+                    stream.read_exact(&mut body).await.unwrap();
+
+                    let request = request_line.split_whitespace().take(2).collect::<Vec<_>>().join(" ");
+                    let (status, headers, json) = route(&request);
+                    log.lock().unwrap().push((request, String::from_utf8(body).unwrap()));
+
+                    let json = json.to_string();
+                    let mut response = format!(
+                        "HTTP/1.1 {} Stub\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n",
+                        status,
+                        json.len()
+                    );
+                    for (name, value) in headers {
+                        response.push_str(&format!("{}: {}\r\n", name, value));
+                    }
+                    response.push_str("\r\n");
+                    response.push_str(&json);
+                    stream.get_mut().write_all(response.as_bytes()).await.unwrap();
+                });
+            }
+        });
+        (server, seen)
+    }
+
+    fn requests(seen: &Seen) -> Vec<String> {
+        seen.lock().unwrap().iter().map(|(request, _)| request.clone()).collect()
+    }
+
+    fn reply(status: u16, body: serde_json::Value) -> Reply {
+        (status, Vec::new(), body)
+    }
+
+    /// x402 challenge whose headers agree with its body
+    fn challenge(asset: &str, amount: u64, base_price: u64, fee: u64) -> Reply {
+        let expiry = now() + 300;
+        let headers = vec![
+            (
+                "WWW-Authenticate",
+                format!(
+                    "x402-RWA realm=\"402-clearinghouse\", circuit=\"{}\", quote_id=\"0xfeed\", \
+                     submit=\"/api/v1/trade/buy/{}\", verify=\"/api/v1/verify\"",
+                    CIRCUIT, asset
+                ),
+            ),
+            ("X-402-Version", X402_VERSION.to_string()),
+            ("X-402-Asset-ID", asset.to_string()),
+            ("X-402-Price", (base_price + fee).to_string()),
+            ("X-402-Base-Price", base_price.to_string()),
+            ("X-402-Fee", fee.to_string()),
+            ("X-402-Payment-Address", CLEARINGHOUSE.to_string()),
+            ("X-402-Expiry", expiry.to_string()),
+            ("X-402-Chain-ID", "8453".to_string()),
+        ];
+        let body = serde_json::json!({
+            "protocol": "x402-RWA/1.0",
+            "asset": asset,
+            "amount": amount,
+            "base_price": base_price,
+            "fee": fee,
+            "total_price": base_price + fee,
+            "currency": "USDC",
+            "expiry": expiry,
+            "quote_id": "0xfeed",
+            "compliance_circuit": CIRCUIT,
+            "payment_address": CLEARINGHOUSE,
+            "chain_id": 8453,
+        });
+        (402, headers, body)
+    }
+
+    /// A healthy server on chain 8453 listing only TBILL-26, at $0.98 plus a 5 bps fee
+    fn clearinghouse(request: &str) -> Reply {
+        let (path, query) = request.split_once('?').unwrap_or((request, ""));
+        let amount: u64 = query.strip_prefix("amount=").and_then(|a| a.parse().ok()).unwrap_or(0);
+        let base_price = amount * 980_000;
+        let fee = (base_price * 5).div_ceil(10_000);
+        match path {
+            "GET /health" => reply(200, serde_json::json!({ "status": "healthy", "chain_id": 8453, "block_number": 1 })),
+            "GET /api/v1/trade/quote/TBILL-26" => reply(
+                200,
+                serde_json::json!({
+                    "asset_id": "TBILL-26",
+                    "amount": amount,
+                    "price_per_unit": 980_000,
+                    "total_price": base_price + fee,
+                    "fee": fee,
+                    "expiry": now() + 300,
+                    "expires_in_seconds": 300,
+                    "quote_id": "0xfeed",
+                }),
+            ),
+            "GET /api/v1/trade/buy/TBILL-26" => challenge("TBILL-26", amount, base_price, fee),
+            "GET /api/v1/compliance/circuit/TBILL-26" => reply(
+                200,
+                serde_json::json!({
+                    "circuit_id": CIRCUIT,
+                    "name": "Accredited investor",
+                    "verifier_address": CLEARINGHOUSE,
+                    "required_claims": ["kyc_verified"],
+                }),
+            ),
+            "POST /api/v1/trade/buy/TBILL-26" => reply(
+                200,
+                serde_json::json!({
+                    "status": "settled",
+                    "tx_hash": format!("0x{}", "ab".repeat(32)),
+                    "asset_delivered": "TBILL-26",
+                    "amount": 5,
+                    "settlement_id": "SETTLE-1",
+                    "timestamp": now(),
+                }),
+            ),
+            _ => {
+                let asset = path.rsplit('/').next().unwrap_or_default();
+                reply(404, serde_json::json!({ "error": format!("Asset not found: {}", asset), "code": 404 }))
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn unlisted_asset_stops_before_proving() {
+        let (server, seen) = stub_server(clearinghouse).await;
+        let (agent, dir) = agent_at(&server, "unlisted");
+        let basket = [BasketItem { asset: "GOLD-1".to_string(), amount: 1 }];
+
+        let outcomes = agent.buy_portfolio(&basket, true).await;
+        assert!(outcomes[0].is_err());
+        assert_eq!(requests(&seen), ["GET /api/v1/trade/quote/GOLD-1?amount=1"]);
+        assert!(!dir.exists(), "no proof was generated");
+    }
+
+    #[tokio::test]
+    async fn challenge_above_the_max_price_is_refused_before_proving() {
+        let (server, seen) = stub_server(|request| match request {
+            // Quoted at $0.98, challenged at $0.99
+            "GET /api/v1/trade/buy/TBILL-26?amount=5" => challenge("TBILL-26", 5, 4_950_000, 2_475),
+            _ => clearinghouse(request),
+        })
+        .await;
+        let (agent, dir) = agent_at(&server, "slippage");
+
+        let err = agent.buy("TBILL-26", 5, true, None).await.unwrap_err();
+        assert!(err.to_string().contains("exceeds max price 4.90245 USDC"), "{}", err);
+        assert!(!requests(&seen).iter().any(|request| request.contains("/compliance/")));
+        assert!(!dir.exists(), "no proof was generated");
+    }
+
+    #[tokio::test]
+    async fn overflowing_max_price_is_an_error() {
+        let (server, _) = stub_server(|request| match request {
+            "GET /api/v1/trade/quote/TBILL-26?amount=5" => {
+                reply(200, serde_json::json!({
+                    "asset_id": "TBILL-26",
+                    "amount": 5,
+                    "price_per_unit": 980_000,
+                    "total_price": u64::MAX / 2,
+                    "fee": 0,
+                    "expiry": now() + 300,
+                    "expires_in_seconds": 300,
+                    "quote_id": "0xfeed",
+                }))
+            }
+            _ => clearinghouse(request),
+        })
+        .await;
+        let agent = Agent::new(&server, None, None, None, 10_000, 8453, true, None).unwrap();
+
+        let err = agent.buy("TBILL-26", 5, true, None).await.unwrap_err();
+        assert!(err.to_string().starts_with("Max price overflows"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn unhealthy_or_wrong_chain_server_aborts_before_the_probe() {
+        for (health, expected) in [
+            (serde_json::json!({ "status": "degraded", "chain_id": 8453, "block_number": 1 }), "degraded health"),
+            (serde_json::json!({ "status": "healthy", "chain_id": 1, "block_number": 1 }), "on chain 1, expected 8453"),
+        ] {
+            let (server, seen) = stub_server(move |request| match request {
+                "GET /health" => reply(200, health.clone()),
+                _ => clearinghouse(request),
+            })
+            .await;
+            let (agent, dir) = agent_at(&server, "preflight");
+
+            let err = agent.buy("TBILL-26", 5, true, Some(u64::MAX)).await.unwrap_err();
+            assert!(err.to_string().contains(expected), "{}", err);
+            assert_eq!(requests(&seen), ["GET /health"]);
+            assert!(!dir.exists(), "no proof was generated");
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_basket_item_does_not_stop_the_rest() {
+        let (server, seen) = stub_server(clearinghouse).await;
+        let (agent, dir) = agent_at(&server, "portfolio");
+        let basket = [
+            BasketItem { asset: "GOLD-1".to_string(), amount: 1 },
+            BasketItem { asset: "TBILL-26".to_string(), amount: 5 },
+        ];
+
+        let outcomes = agent.buy_portfolio(&basket, true).await;
+        assert_eq!(outcomes.len(), 2);
+        assert!(outcomes[0].is_err());
+        let receipt = outcomes[1].as_ref().unwrap();
+        assert_eq!(receipt.quote.asset_id, "TBILL-26");
+        assert_eq!(receipt.settlement.status, "dry_run");
+        assert!(requests(&seen).contains(&"GET /api/v1/trade/buy/TBILL-26?amount=5".to_string()));
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn challenge_for_another_amount_is_refused_before_signing() {
+        let (server, seen) = stub_server(|request| match request {
+            "GET /api/v1/trade/buy/TBILL-26?amount=5" => {
+                // Terms for 6 units in answer to a request for 5
+                let (status, headers, mut body) = challenge("TBILL-26", 5, 4_900_000, 2_450);
+                body["amount"] = 6.into();
+                (status, headers, body)
+            }
+            _ => clearinghouse(request),
+        })
+        .await;
+        let signer: LocalWallet = AGENT_KEY.parse().unwrap();
+        let agent = Agent::new(&server, Some(signer), None, None, 0, 8453, true, None).unwrap();
+
+        let err = agent.buy("TBILL-26", 5, false, None).await.unwrap_err();
+        assert!(err.to_string().contains("disagrees with its headers on amount"), "{}", err);
+        assert!(!requests(&seen).iter().any(|request| request.starts_with("POST ")));
+    }
+
+    #[tokio::test]
+    async fn server_errors_are_shown_with_their_code() {
+        let (server, _) = stub_server(|request| match request {
+            "POST /api/v1/trade/buy/TBILL-26" => {
+                reply(400, serde_json::json!({ "error": "Invalid request: quote mismatch", "code": 400 }))
+            }
+            "GET /api/v1/agent/0xabc/status" => {
+                reply(503, serde_json::json!({ "error": "Blockchain error: rpc timeout", "code": 503 }))
+            }
+            _ => clearinghouse(request),
+        })
+        .await;
+        let signer: LocalWallet = AGENT_KEY.parse().unwrap();
+        let agent = Agent::new(&server, Some(signer), None, None, 0, 8453, true, None).unwrap();
+
+        let err = agent.get_quote("GOLD-1", 1).await.unwrap_err();
+        assert_eq!(err.to_string(), "Fetching quote failed: Asset not found: GOLD-1 (code 404)");
+        let err = agent.get_status("0xabc").await.unwrap_err();
+        assert_eq!(err.to_string(), "Fetching agent status failed: Blockchain error: rpc timeout (code 503)");
+        let err = agent.buy("TBILL-26", 5, false, Some(u64::MAX)).await.unwrap_err();
+        assert!(err.to_string().contains("Invalid request: quote mismatch (code 400)"), "{}", err);
+    }
+
+    #[tokio::test]
+    async fn signing_key_sets_the_address_the_proof_binds() {
+        let (server, seen) = stub_server(clearinghouse).await;
+        let agent = Agent::new(&server, Some(AGENT_KEY.parse().unwrap()), None, None, 0, 8453, true, None).unwrap();
+        assert_eq!(agent.wallet_address, "0x70997970C51812dc3A010C7d01b50e0d17dc79C8");
+
+        agent.buy("TBILL-26", 5, false, None).await.unwrap();
+        let seen = seen.lock().unwrap();
+        let (_, submitted) = seen.iter().find(|(request, _)| request.starts_with("POST ")).unwrap();
+        let submitted: serde_json::Value = serde_json::from_str(submitted).unwrap();
+        let public_values = hex::decode(submitted["public_values"].as_str().unwrap().trim_start_matches("0x")).unwrap();
+        assert_eq!(public_values[..12], [0u8; 12]);
+        assert_eq!(
+            to_checksum(&Address::from_slice(&public_values[12..32]), None),
+            agent.wallet_address
+        );
+    }
+}
@@ -2,15 +2,26 @@
 //!
 //! Generates ZK proofs for agent identity verification.
 //! Usage: cargo run --release -- --secret "hello" --proof-file proof.json
+//!
+//! Pass `--elf <path>` (or set `IDENTITY_ELF`) to prove with a freshly built
+//! or alternate circuit instead of the embedded one, and `--execute-only` to
+//! report the cycle count without proving.
 
-use sp1_sdk::{ProverClient, SP1Stdin};
+use sp1_sdk::{HashableKey, ProverClient, SP1Stdin};
 use clap::Parser;
 use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
 use std::fs;
 
-/// The ELF binary of the identity circuit
+/// The ELF binary of the identity circuit, used unless `--elf` is given
 const ELF: &[u8] = include_bytes!("../../program/elf/riscv32im-succinct-zkvm-elf");
 
+/// Approximate gas for the SP1 Groth16 verifier's pairing check
+const GROTH16_VERIFY_GAS: u64 = 270_000;
+
+/// Base transaction gas
+const TX_BASE_GAS: u64 = 21_000;
+
 #[derive(Parser, Debug)]
 #[command(author, version, about, long_about = None)]
 struct Args {
@@ -25,6 +36,14 @@ struct Args {
     /// Output file for the ZK proof
     #[arg(short, long, default_value = "zk_proof.bin")]
     output: String,
+
+    /// Circuit ELF to prove with (defaults to the embedded build)
+    #[arg(long, env = "IDENTITY_ELF")]
+    elf: Option<String>,
+
+    /// Only execute the program: print its cycle count and public values
+    #[arg(long)]
+    execute_only: bool,
 }
 
 #[derive(Debug, Serialize, Deserialize)]
@@ -33,12 +52,67 @@ struct MerkleProof {
     directions: Vec<bool>,
 }
 
+/// The circuit ELF: read from `path` if given, else the embedded one
+fn load_elf(path: Option<&str>) -> Result<Cow<'static, [u8]>, String> {
+    let Some(path) = path else {
+        return Ok(Cow::Borrowed(ELF));
+    };
+    let bytes = fs::read(path).map_err(|e| format!("Cannot read ELF {}: {}", path, e))?;
+    if !bytes.starts_with(b"\x7fELF") {
+        return Err(format!("{} is not an ELF binary", path));
+    }
+    Ok(Cow::Owned(bytes))
+}
+
+/// ABI calldata for `verifyProof(bytes32 programVKey, bytes publicValues, bytes proofBytes)`
+fn verify_calldata(vkey: [u8; 32], public_values: &[u8], proof: &[u8]) -> Vec<u8> {
+    fn word(value: usize) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        word[24..].copy_from_slice(&(value as u64).to_be_bytes());
+        word
+    }
+    fn padded_len(len: usize) -> usize {
+        len.div_ceil(32) * 32
+    }
+
+    let mut calldata = Vec::new();
+    calldata.extend_from_slice(&[0x41, 0x49, 0x3c, 0x60]); // verifyProof(bytes32,bytes,bytes)
+    calldata.extend_from_slice(&vkey);
+    // Head: offsets of the two dynamic arguments, relative to the start of the args
+    calldata.extend_from_slice(&word(3 * 32));
+    calldata.extend_from_slice(&word(3 * 32 + 32 + padded_len(public_values.len())));
+    for bytes in [public_values, proof] {
+        calldata.extend_from_slice(&word(bytes.len()));
+        calldata.extend_from_slice(bytes);
+        calldata.resize(calldata.len() + padded_len(bytes.len()) - bytes.len(), 0);
+    }
+    calldata
+}
+
+/// Rough gas to verify on-chain: base tx + calldata (EIP-2028) + pairing check
+fn estimate_verify_gas(calldata: &[u8]) -> u64 {
+    let calldata_gas: u64 = calldata
+        .iter()
+        .map(|&b| if b == 0 { 4 } else { 16 })
+        .sum();
+    TX_BASE_GAS + calldata_gas + GROTH16_VERIFY_GAS
+}
+
 fn main() {
     sp1_sdk::utils::setup_logger();
 
     let args = Args::parse();
 
+    // Fail on a bad ELF path before any proving work starts
+    let elf = load_elf(args.elf.as_deref()).unwrap_or_else(|e| {
+        eprintln!("[!] {}", e);
+        std::process::exit(1);
+    });
+
     println!("[*] x402 Identity Prover (Merkle Tree)");
+    if let Some(path) = &args.elf {
+        println!("[*] Using circuit ELF: {}", path);
+    }
     println!("[*] Loading Merkle proof from: {}", args.proof_file);
 
     // Load Merkle proof from file
@@ -59,7 +133,9 @@ fn main() {
     };
 
     println!("[*] Proof has {} siblings", proof_data.siblings.len());
-    println!("[*] Generating ZK proof...");
+    if !args.execute_only {
+        println!("[*] Generating ZK proof...");
+    }
 
     // Initialize the prover client
     let client = ProverClient::from_env();
@@ -70,8 +146,21 @@ fn main() {
     stdin.write(&proof_data.siblings);
     stdin.write(&proof_data.directions);
 
+    // Executing is far cheaper than proving, for sizing circuit inputs
+    if args.execute_only {
+        let (public_values, report) = client
+            .execute(&elf, &stdin)
+            .run()
+            .expect("Failed to execute program");
+        println!("[+] Executed without proving");
+        println!("    Cycles:         {}", report.total_instruction_count());
+        println!("    Public values:  {} bytes", public_values.as_slice().len());
+        println!("    0x{}", hex::encode(public_values.as_slice()));
+        return;
+    }
+
     // Generate the proof
-    let (pk, vk) = client.setup(ELF);
+    let (pk, vk) = client.setup(&elf);
     let proof = client
         .prove(&pk, &stdin)
         .groth16()
@@ -92,7 +181,73 @@ fn main() {
     println!("[+] ZK Proof saved to: {}", args.output);
     println!("[+] Proof size: {} bytes", proof_bytes.len());
 
+    // What a verifier contract actually receives, not the serialized bundle
+    let onchain_proof = proof.bytes();
+    let public_values = proof.public_values.to_vec();
+    let vkey: [u8; 32] = hex::decode(vk.bytes32().trim_start_matches("0x"))
+        .expect("Invalid vkey hash")
+        .try_into()
+        .expect("vkey hash is not 32 bytes");
+    let calldata = verify_calldata(vkey, &public_values, &onchain_proof);
+
+    println!("\n[*] On-chain verification cost:");
+    println!("    Proof bytes:    {} bytes", onchain_proof.len());
+    println!("    Public values:  {} bytes", public_values.len());
+    println!("    Calldata:       {} bytes", calldata.len());
+    println!("    Estimated gas:  ~{}", estimate_verify_gas(&calldata));
+
     // Output for on-chain submission
     println!("\n[*] For on-chain submission:");
     println!("0x{}", hex::encode(&proof_bytes[..64.min(proof_bytes.len())]));
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn word_at(calldata: &[u8], index: usize) -> &[u8] {
+        &calldata[4 + 32 * index..4 + 32 * (index + 1)]
+    }
+
+    fn word(value: u8) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        word[31] = value;
+        word
+    }
+
+    #[test]
+    fn calldata_pads_unaligned_public_values() {
+        let public_values = [0x11u8; 33];
+        let proof = [0x22u8; 10];
+        let calldata = verify_calldata([0x33; 32], &public_values, &proof);
+
+        assert_eq!(calldata[..4], [0x41, 0x49, 0x3c, 0x60]);
+        assert_eq!(word_at(&calldata, 0), [0x33; 32]);
+        // publicValues right after the head, proofBytes after its length word and 64 padded bytes
+        assert_eq!(word_at(&calldata, 1), word(0x60));
+        assert_eq!(word_at(&calldata, 2), word(0x60 + 32 + 64));
+        assert_eq!(word_at(&calldata, 3), word(33));
+        assert_eq!(word_at(&calldata, 6), word(10));
+        // selector + vkey + two offsets + (length + 64) + (length + 32)
+        assert_eq!(calldata.len(), 4 + 3 * 32 + 96 + 64);
+    }
+
+    #[test]
+    fn unreadable_or_non_elf_path_is_named_in_the_error() {
+        let missing = std::env::temp_dir().join("x402-no-such-identity.elf");
+        let missing = missing.to_str().unwrap();
+        let err = load_elf(Some(missing)).unwrap_err();
+        assert!(err.starts_with(&format!("Cannot read ELF {}", missing)), "{}", err);
+
+        let not_elf = std::env::temp_dir().join(format!("x402-not-an-elf-{}", std::process::id()));
+        fs::write(&not_elf, b"#!/bin/sh\n").unwrap();
+        let not_elf = not_elf.to_str().unwrap();
+        assert_eq!(load_elf(Some(not_elf)).unwrap_err(), format!("{} is not an ELF binary", not_elf));
+        fs::remove_file(not_elf).unwrap();
+    }
+
+    #[test]
+    fn no_path_loads_the_embedded_elf() {
+        assert_eq!(&*load_elf(None).unwrap(), ELF);
+    }
+}
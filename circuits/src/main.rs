@@ -7,7 +7,8 @@
 //!
 //! All without revealing the operator's identity on-chain.
 
-#![no_main]
+#![cfg_attr(not(test), no_main)]
+#[cfg(not(test))]
 sp1_zkvm::entrypoint!(main);
 
 use sha2::{Sha256, Digest};
@@ -19,9 +20,12 @@ struct PrivateInputs {
     /// Operator's identity commitment (hash of PII)
     identity_commitment: [u8; 32],
     
-    /// KYC provider's signature over the identity
+    /// KYC provider's signature over the identity and jurisdiction
     kyc_signature: [u8; 64],
     
+    /// ISO 3166-1 alpha-2 country code attested by the KYC provider (e.g. b"US")
+    jurisdiction_code: [u8; 2],
+    
     /// Accreditation attestation data
     accreditation_proof: AccreditationProof,
     
@@ -83,7 +87,7 @@ struct PublicOutputs {
     /// Timestamp when verification expires
     valid_until: u64,
     
-    /// Hash of jurisdiction (for regulatory compliance)
+    /// H(jurisdiction_code || salt), identical for every agent in a country
     jurisdiction_hash: [u8; 32],
     
     /// Commitment to the identity (for audit trails)
@@ -104,6 +108,9 @@ const TRUSTED_KYC_PROVIDERS: &[[u8; 33]] = &[
      0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x02],
 ];
 
+/// Public salt for jurisdiction hashes, so contracts can allowlist countries
+const JURISDICTION_SALT: &[u8] = b"X402_JURISDICTION_V1";
+
 /// Maximum age of sanctions check (30 days)
 const MAX_SANCTIONS_AGE: u64 = 30 * 24 * 60 * 60;
 
@@ -118,8 +125,8 @@ fn main() {
     let current_time: u64 = sp1_zkvm::io::read();
     
     // 1. VERIFY KYC SIGNATURE
-    // The KYC provider has signed: H(identity_commitment || "KYC_VERIFIED")
-    let kyc_message = compute_kyc_message(&inputs.identity_commitment);
+    // The KYC provider has signed: H(identity_commitment || jurisdiction_code || "KYC_VERIFIED")
+    let kyc_message = compute_kyc_message(&inputs.identity_commitment, &inputs.jurisdiction_code);
     assert!(
         verify_provider_signature(&kyc_message, &inputs.kyc_signature),
         "Invalid KYC signature"
@@ -168,8 +175,8 @@ fn main() {
     );
     
     // 5. COMPUTE JURISDICTION HASH
-    // Derived from identity commitment in a privacy-preserving way
-    let jurisdiction_hash = compute_jurisdiction_hash(&inputs.identity_commitment);
+    // Bound to the attested country, not the identity, so it can be allowlisted
+    let jurisdiction_hash = compute_jurisdiction_hash(&inputs.jurisdiction_code);
     
     // 6. COMMIT PUBLIC OUTPUTS
     let public_outputs = PublicOutputs {
@@ -184,10 +191,11 @@ fn main() {
 }
 
 /// Compute the message that KYC providers sign
-fn compute_kyc_message(identity_commitment: &[u8; 32]) -> [u8; 32] {
+fn compute_kyc_message(identity_commitment: &[u8; 32], jurisdiction_code: &[u8; 2]) -> [u8; 32] {
     let mut hasher = Sha256::new();
     hasher.update(identity_commitment);
-    hasher.update(b"KYC_VERIFIED_V1");
+    hasher.update(jurisdiction_code);
+    hasher.update(b"KYC_VERIFIED_V2");
     hasher.finalize().into()
 }
 
@@ -254,10 +262,37 @@ fn verify_merkle_exclusion(
     current.as_slice() != root.as_slice()
 }
 
-/// Compute jurisdiction hash from identity commitment
-fn compute_jurisdiction_hash(identity: &[u8; 32]) -> [u8; 32] {
+/// Compute jurisdiction hash from the attested country code
+fn compute_jurisdiction_hash(jurisdiction_code: &[u8; 2]) -> [u8; 32] {
     let mut hasher = Sha256::new();
-    hasher.update(identity);
-    hasher.update(b"JURISDICTION_V1");
+    hasher.update(jurisdiction_code);
+    hasher.update(JURISDICTION_SALT);
     hasher.finalize().into()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn digest(hex: &str) -> [u8; 32] {
+        let mut out = [0u8; 32];
+        for (i, byte) in out.iter_mut().enumerate() {
+            *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16).unwrap();
+        }
+        out
+    }
+
+    /// Same digests tests/e2e_test.py builds as sha256(code || "X402_JURISDICTION_V1")
+    #[test]
+    fn jurisdiction_hash_is_the_salted_country_code() {
+        assert_eq!(JURISDICTION_SALT, b"X402_JURISDICTION_V1");
+        assert_eq!(
+            compute_jurisdiction_hash(b"US"),
+            digest("7ae8c13dc8f1d0186d094782d9a8bbf745404a621de1b0561727c2e4fc2bda83")
+        );
+        assert_eq!(
+            compute_jurisdiction_hash(b"UK"),
+            digest("c1107354294df2cc98988f908c61b93533be0227cd1f788587833321ed58ce02")
+        );
+    }
+}
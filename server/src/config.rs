@@ -12,6 +12,26 @@ pub struct Config {
     pub usdc_address: String,
     pub private_key: Option<String>, // For relay transactions
     pub quote_validity_seconds: u64,
+    pub default_fee_bps: u64, // Used when the contract's feeBps() can't be read
+    pub min_fee_usdc: u64,    // Floor on any quoted fee, in atomic USDC
+    pub indexer_start_block: u64,
+    pub finality_depth: u64,
+    pub rpc_timeout_secs: u64,
+    pub confirmations: u64, // Blocks, inclusion included, before a settlement is final
+    pub current_time_tolerance_secs: u64, // Allowed skew of a proof's committed current_time
+    pub health_stale_secs: u64, // Health is degraded once the chain head is this old
+    pub max_body_bytes: usize,  // Larger request bodies are rejected with 413
+    pub allowed_origins: Vec<String>, // ["*"] allows any origin
+    pub admin_api_key: Option<String>, // Admin endpoints are disabled when unset
+    pub tls: Option<TlsConfig>,         // Serve HTTPS when set
+    pub vk_dir: Option<String>,        // One verification key file per circuit id
+}
+
+/// PEM certificate chain and private key for HTTPS
+#[derive(Clone, Debug)]
+pub struct TlsConfig {
+    pub cert_path: String,
+    pub key_path: String,
 }
 
 impl Config {
@@ -42,6 +62,69 @@ impl Config {
                 .unwrap_or_else(|_| "300".to_string()) // 5 minutes
                 .parse()
                 .context("Invalid QUOTE_VALIDITY_SECONDS")?,
+            
+            default_fee_bps: env::var("DEFAULT_FEE_BPS")
+                .unwrap_or_else(|_| "5".to_string()) // 0.05%
+                .parse()
+                .context("Invalid DEFAULT_FEE_BPS")?,
+            
+            min_fee_usdc: env::var("MIN_FEE_USDC")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .context("Invalid MIN_FEE_USDC")?,
+            
+            indexer_start_block: env::var("INDEXER_START_BLOCK")
+                .unwrap_or_else(|_| "0".to_string())
+                .parse()
+                .context("Invalid INDEXER_START_BLOCK")?,
+            
+            finality_depth: env::var("FINALITY_DEPTH")
+                .unwrap_or_else(|_| "64".to_string())
+                .parse()
+                .context("Invalid FINALITY_DEPTH")?,
+            
+            rpc_timeout_secs: env::var("RPC_TIMEOUT_SECS")
+                .unwrap_or_else(|_| "10".to_string())
+                .parse()
+                .context("Invalid RPC_TIMEOUT_SECS")?,
+            
+            confirmations: env::var("CONFIRMATIONS")
+                .unwrap_or_else(|_| "1".to_string())
+                .parse::<u64>()
+                .context("Invalid CONFIRMATIONS")?
+                .max(1),
+            
+            current_time_tolerance_secs: env::var("CURRENT_TIME_TOLERANCE_SECS")
+                .unwrap_or_else(|_| "120".to_string())
+                .parse()
+                .context("Invalid CURRENT_TIME_TOLERANCE_SECS")?,
+            
+            health_stale_secs: env::var("HEALTH_STALE_SECS")
+                .unwrap_or_else(|_| "30".to_string())
+                .parse()
+                .context("Invalid HEALTH_STALE_SECS")?,
+            
+            max_body_bytes: env::var("MAX_BODY_BYTES")
+                .unwrap_or_else(|_| "65536".to_string())
+                .parse()
+                .context("Invalid MAX_BODY_BYTES")?,
+            
+            allowed_origins: env::var("ALLOWED_ORIGINS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|origin| origin.trim().to_string())
+                .filter(|origin| !origin.is_empty())
+                .collect(),
+            
+            admin_api_key: env::var("ADMIN_API_KEY").ok().filter(|key| !key.is_empty()),
+            
+            tls: match (env::var("TLS_CERT_PATH").ok(), env::var("TLS_KEY_PATH").ok()) {
+                (Some(cert_path), Some(key_path)) => Some(TlsConfig { cert_path, key_path }),
+                (None, None) => None,
+                _ => anyhow::bail!("TLS_CERT_PATH and TLS_KEY_PATH must be set together"),
+            },
+            
+            vk_dir: env::var("VK_DIR").ok().filter(|dir| !dir.is_empty()),
         })
     }
 }
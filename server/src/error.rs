@@ -13,15 +13,29 @@ pub enum AppError {
     #[error("Asset not found: {0}")]
     AssetNotFound(String),
     
+    #[error("Compliance circuit not registered: {0}")]
+    CircuitNotFound(String),
+    
+    #[error("Settlement not found: {0}")]
+    SettlementNotFound(String),
+    
     #[error("Quote expired")]
     QuoteExpired,
     
     #[error("Invalid proof")]
     InvalidProof,
     
-    #[error("Insufficient balance")]
-    InsufficientBalance,
+    #[error("Unauthorized: {0}")]
+    Unauthorized(String),
+    
+    #[error("Insufficient USDC balance: {shortfall} short of {required} (atomic units)")]
+    InsufficientBalance { required: u64, shortfall: u64 },
+    
+    #[error("Insufficient USDC allowance for the clearinghouse: {shortfall} short of {required} (atomic units)")]
+    InsufficientAllowance { required: u64, shortfall: u64 },
     
+    /// Only the Alloy service sends transactions
+    #[cfg(feature = "alloy")]
     #[error("Transaction failed: {0}")]
     TransactionFailed(String),
     
@@ -31,6 +45,18 @@ pub enum AppError {
     #[error("Invalid request: {0}")]
     BadRequest(String),
     
+    #[error("Invalid proof encoding: {0}")]
+    InvalidProofEncoding(String),
+    
+    #[error("Invalid public values encoding: {0}")]
+    InvalidPublicValuesEncoding(String),
+    
+    #[error("Not acceptable: {0}")]
+    NotAcceptable(String),
+    
+    #[error("Unsupported content type: {0}")]
+    UnsupportedMediaType(String),
+    
     #[error("Internal error: {0}")]
     Internal(String),
 }
@@ -39,12 +65,21 @@ impl IntoResponse for AppError {
     fn into_response(self) -> Response {
         let (status, error_message) = match &self {
             AppError::AssetNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            AppError::CircuitNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
+            AppError::SettlementNotFound(_) => (StatusCode::NOT_FOUND, self.to_string()),
             AppError::QuoteExpired => (StatusCode::GONE, self.to_string()),
             AppError::InvalidProof => (StatusCode::UNAUTHORIZED, self.to_string()),
-            AppError::InsufficientBalance => (StatusCode::PAYMENT_REQUIRED, self.to_string()),
+            AppError::Unauthorized(_) => (StatusCode::UNAUTHORIZED, self.to_string()),
+            AppError::InsufficientBalance { .. } => (StatusCode::PAYMENT_REQUIRED, self.to_string()),
+            AppError::InsufficientAllowance { .. } => (StatusCode::PAYMENT_REQUIRED, self.to_string()),
+            #[cfg(feature = "alloy")]
             AppError::TransactionFailed(_) => (StatusCode::BAD_REQUEST, self.to_string()),
             AppError::BlockchainError(_) => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
             AppError::BadRequest(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            AppError::InvalidProofEncoding(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            AppError::InvalidPublicValuesEncoding(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            AppError::NotAcceptable(_) => (StatusCode::NOT_ACCEPTABLE, self.to_string()),
+            AppError::UnsupportedMediaType(_) => (StatusCode::UNSUPPORTED_MEDIA_TYPE, self.to_string()),
             AppError::Internal(_) => (StatusCode::INTERNAL_SERVER_ERROR, "Internal error".to_string()),
         };
 
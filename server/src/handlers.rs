@@ -3,45 +3,100 @@
 use axum::{
     extract::{Path, Query, State},
     http::{header, HeaderMap, StatusCode},
-    response::IntoResponse,
+    response::{
+        sse::{Event, KeepAlive, Sse},
+        IntoResponse,
+    },
     Json,
 };
+use anyhow::Context;
+use ethers::signers::LocalWallet;
 use serde::Deserialize;
+use std::convert::Infallible;
 use std::sync::Arc;
-use std::time::{SystemTime, UNIX_EPOCH};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio_stream::{wrappers::BroadcastStream, Stream, StreamExt};
 
 use crate::config::Config;
 use crate::error::AppError;
 use crate::models::*;
+use crate::services::auth;
 use crate::services::blockchain::BlockchainService;
+use crate::services::chain_head::ChainHead;
+use crate::services::indexer::{SettlementEvent, SettlementIndexer};
+use crate::services::quotes::{StoredQuote, Stores};
+use crate::services::registry::AssetRegistry;
+use crate::services::verifier::{ProofVerifier, Verification};
+use crate::services::vk_registry::VkRegistry;
 
 /// Shared application state
 #[derive(Clone)]
 pub struct AppState {
     pub config: Config,
     pub blockchain: Arc<BlockchainService>,
+    pub indexer: Arc<SettlementIndexer>,
+    pub chain_head: Arc<ChainHead>,
+    pub stores: Stores,
+    pub registry: Arc<dyn AssetRegistry>,
+    pub vk_registry: Arc<VkRegistry>,
+    pub verifier: Arc<ProofVerifier>,
+    /// Relay key used to sign x402 quotes, if configured
+    pub quote_signer: Option<LocalWallet>,
 }
 
 impl AppState {
-    pub fn new(config: Config, blockchain: BlockchainService) -> Self {
-        Self {
+    pub fn new(
+        config: Config,
+        blockchain: BlockchainService,
+        registry: Arc<dyn AssetRegistry>,
+    ) -> anyhow::Result<Self> {
+        let indexer = SettlementIndexer::new(&config);
+        let vk_registry = Arc::new(VkRegistry::load(config.vk_dir.as_deref().map(std::path::Path::new))?);
+        let verifier = ProofVerifier::new(vk_registry.clone(), config.current_time_tolerance_secs);
+        let quote_signer = config
+            .private_key
+            .as_deref()
+            .map(|pk| pk.parse::<LocalWallet>().context("Invalid RELAY_PRIVATE_KEY"))
+            .transpose()?;
+        
+        Ok(Self {
             config,
             blockchain: Arc::new(blockchain),
-        }
+            indexer: Arc::new(indexer),
+            chain_head: Arc::new(ChainHead::new()),
+            stores: Stores::in_memory(),
+            registry,
+            vk_registry,
+            verifier: Arc::new(verifier),
+            quote_signer,
+        })
     }
 }
 
 /// Health check endpoint
-pub async fn health(State(state): State<AppState>) -> Result<Json<HealthResponse>, AppError> {
-    let block_number = state.blockchain.get_block_number().await?;
+///
+/// Reads the cached chain head rather than the RPC, and answers 503
+/// `degraded` once the last successful poll is older than `HEALTH_STALE_SECS`.
+pub async fn health(State(state): State<AppState>) -> (StatusCode, Json<HealthResponse>) {
+    let latest = state.chain_head.latest();
+    let fresh = latest.is_some_and(|(_, age)| age.as_secs() <= state.config.health_stale_secs);
+    let (status_code, status) = if fresh {
+        (StatusCode::OK, "healthy")
+    } else {
+        (StatusCode::SERVICE_UNAVAILABLE, "degraded")
+    };
     
-    Ok(Json(HealthResponse {
-        status: "healthy".to_string(),
-        chain_id: state.config.chain_id,
-        block_number,
-        clearinghouse: state.config.clearinghouse_address.clone(),
-        version: env!("CARGO_PKG_VERSION").to_string(),
-    }))
+    (
+        status_code,
+        Json(HealthResponse {
+            status: status.to_string(),
+            chain_id: state.config.chain_id,
+            block_number: latest.map_or(0, |(block_number, _)| block_number),
+            block_age_secs: latest.map(|(_, age)| age.as_secs()),
+            clearinghouse: state.config.clearinghouse_address.clone(),
+            version: env!("CARGO_PKG_VERSION").to_string(),
+        }),
+    )
 }
 
 /// List all available assets
@@ -63,119 +118,438 @@ pub async fn get_asset(
     Ok(Json(asset))
 }
 
+/// Size of a quote: either `amount` units or `value_usdc` worth of them
 #[derive(Debug, Deserialize)]
 pub struct QuoteQuery {
-    pub amount: u64,
+    pub amount: Option<u64>,
+    /// Atomic USDC to spend before fees, bought as whole units
+    pub value_usdc: Option<u64>,
 }
 
-/// Get a quote for an asset purchase
-pub async fn get_quote(
-    State(state): State<AppState>,
-    Path(asset): Path<String>,
-    Query(query): Query<QuoteQuery>,
-) -> Result<Json<Quote>, AppError> {
+impl QuoteQuery {
+    /// Units to quote, and the USDC left over when sized by value
+    fn resolve(&self, asset: &Asset) -> Result<(u64, Option<u64>), AppError> {
+        match (self.amount, self.value_usdc) {
+            (Some(amount), None) => Ok((amount, None)),
+            (None, Some(value)) => {
+                // A zero price is rejected by quote_subtotal
+                let price = asset.price_per_unit.max(1);
+                if value < price {
+                    return Err(AppError::BadRequest(format!(
+                        "value_usdc {} is less than one unit ({})",
+                        value, price
+                    )));
+                }
+                Ok((value / price, Some(value % price)))
+            }
+            _ => Err(AppError::BadRequest(
+                "Exactly one of amount or value_usdc is required".to_string(),
+            )),
+        }
+    }
+}
+
+/// Bounds on quote validity, global or per asset
+const MIN_QUOTE_VALIDITY_SECONDS: u64 = 10;
+const MAX_QUOTE_VALIDITY_SECONDS: u64 = 60 * 60;
+
+/// Quote validity for an asset, falling back to the global setting
+fn quote_validity(state: &AppState, asset: &Asset) -> u64 {
+    asset
+        .quote_validity_seconds
+        .unwrap_or(state.config.quote_validity_seconds)
+        .clamp(MIN_QUOTE_VALIDITY_SECONDS, MAX_QUOTE_VALIDITY_SECONDS)
+}
+
+/// Expiry of a quote issued at `now`
+///
+/// Time is split into fixed windows of `validity` seconds and every quote
+/// issued in a window expires at its end, so repeated quotes for the same
+/// asset and amount share a `quote_id`. A quote issued too close to the end
+/// of its window moves to the next one, keeping at least
+/// `MIN_QUOTE_VALIDITY_SECONDS` to act on it. A quote is therefore valid for
+/// between `MIN_QUOTE_VALIDITY_SECONDS` and `validity + MIN_QUOTE_VALIDITY_SECONDS`
+/// (exclusive), not exactly `validity`.
+fn quote_expiry(now: u64, validity: u64) -> u64 {
+    let validity = validity.max(MIN_QUOTE_VALIDITY_SECONDS);
+    let window_end = (now / validity + 1) * validity;
+    if window_end - now < MIN_QUOTE_VALIDITY_SECONDS {
+        window_end + validity
+    } else {
+        window_end
+    }
+}
+
+/// Largest amount a single quote can be issued for
+const MAX_QUOTE_AMOUNT: u64 = 1_000_000_000_000;
+
+/// Price before fees of `amount` units, rejecting quotes that can't settle
+fn quote_subtotal(amount: u64, asset: &Asset) -> Result<u64, AppError> {
+    if amount == 0 {
+        return Err(AppError::BadRequest("amount must be positive".to_string()));
+    }
+    if amount > MAX_QUOTE_AMOUNT {
+        return Err(AppError::BadRequest(format!(
+            "amount must be at most {}",
+            MAX_QUOTE_AMOUNT
+        )));
+    }
+    if let Some(min) = asset.min_amount.filter(|&min| amount < min) {
+        return Err(AppError::BadRequest(format!(
+            "amount must be at least {} for {}",
+            min, asset.id
+        )));
+    }
+    if let Some(max) = asset.max_amount.filter(|&max| amount > max) {
+        return Err(AppError::BadRequest(format!(
+            "amount must be at most {} for {}",
+            max, asset.id
+        )));
+    }
+    if asset.price_per_unit == 0 {
+        return Err(AppError::BadRequest(format!(
+            "Asset {} is misconfigured: price_per_unit is zero",
+            asset.id
+        )));
+    }
+    amount
+        .checked_mul(asset.price_per_unit)
+        .ok_or_else(|| AppError::BadRequest("amount too large for this asset's price".to_string()))
+}
+
+/// Fee on a quote's base price, in atomic USDC
+///
+/// Rounds up so small trades never pay less than `fee_bps` (a truncated fee
+/// would round them to zero), and never charges less than `min_fee`.
+pub fn compute_fee(base_price: u64, fee_bps: u64, min_fee: u64) -> u64 {
+    let fee = (base_price as u128 * fee_bps as u128).div_ceil(10_000);
+    u64::try_from(fee).unwrap_or(u64::MAX).max(min_fee)
+}
+
+/// Fee the contract will charge, falling back to the configured default
+async fn current_fee_bps(state: &AppState) -> u64 {
+    match state.blockchain.get_fee_bps().await {
+        Ok(fee_bps) => fee_bps,
+        Err(e) => {
+            tracing::warn!("Using default fee, feeBps() read failed: {}", e);
+            state.config.default_fee_bps
+        }
+    }
+}
+
+/// Quote identifier shared by `get_quote` and `buy_challenge`
+///
+/// The same asset, amount, expiry and chain always yield the same id, so a
+/// quote can be correlated with the challenge and settlement that follow it.
+/// Expiries are aligned to validity windows (see `quote_expiry`), making the
+/// id stable for the whole window.
+///
+/// `asset_id` must be the listed id, not the caller's spelling, or one asset
+/// named by id, symbol and address would get three ids.
+pub fn compute_quote_id(asset_id: &str, amount: u64, expiry: u64, chain_id: u64) -> String {
+    let mut hasher = sha2::Sha256::new();
+    hasher.update(asset_id.as_bytes());
+    hasher.update(amount.to_be_bytes());
+    hasher.update(expiry.to_be_bytes());
+    hasher.update(chain_id.to_be_bytes());
+    format!("{:x}", hasher.finalize())
+}
+
+/// A priced quote, already recorded in the quote store
+struct IssuedQuote {
+    /// Asset as the caller named it
+    asset: String,
+    asset_info: Asset,
+    amount: u64,
+    remainder_usdc: Option<u64>,
+    base_price: u64,
+    /// Contract fee rate the fee was priced at
+    fee_bps: u64,
+    fee: u64,
+    /// `base_price + fee`, checked for overflow once here
+    total_price: u64,
+    expiry: u64,
+    quote_id: String,
+    now: u64,
+}
+
+/// Price and record a quote, shared by `get_quote` and `buy_challenge`
+async fn issue_quote(
+    state: &AppState,
+    asset: &str,
+    query: &QuoteQuery,
+) -> Result<IssuedQuote, AppError> {
     let asset_info = state
         .blockchain
-        .get_asset(&asset)
+        .get_asset(asset)
         .await?
-        .ok_or_else(|| AppError::AssetNotFound(asset.clone()))?;
+        .ok_or_else(|| AppError::AssetNotFound(asset.to_string()))?;
+    let (amount, remainder_usdc) = query.resolve(&asset_info)?;
     
     let now = SystemTime::now()
         .duration_since(UNIX_EPOCH)
         .unwrap()
         .as_secs();
     
-    let total_price = query.amount * asset_info.price_per_unit;
-    let fee = total_price * 5 / 10_000; // 0.05% fee
+    let base_price = quote_subtotal(amount, &asset_info)?;
+    let fee_bps = current_fee_bps(state).await;
+    let fee = compute_fee(base_price, fee_bps, state.config.min_fee_usdc);
+    let total_price = base_price
+        .checked_add(fee)
+        .ok_or_else(|| AppError::BadRequest("Total price overflows".to_string()))?;
+    let expiry = quote_expiry(now, quote_validity(state, &asset_info));
     
-    let quote_id = format!(
-        "{:x}",
-        sha2::Sha256::digest(format!("{}{}{}{}", asset, query.amount, now, state.config.chain_id))
+    let quote_id = compute_quote_id(&asset_info.id, amount, expiry, state.config.chain_id);
+    state.stores.quotes.insert(
+        &quote_id,
+        StoredQuote {
+            asset_id: asset_info.id.clone(),
+            amount,
+            total_price,
+            expiry,
+        },
+        now,
     );
     
-    Ok(Json(Quote {
-        asset_id: asset,
-        amount: query.amount,
-        price_per_unit: asset_info.price_per_unit,
-        total_price: total_price + fee,
+    Ok(IssuedQuote {
+        asset: asset.to_string(),
+        asset_info,
+        amount,
+        remainder_usdc,
+        base_price,
+        fee_bps,
         fee,
-        expiry: now + state.config.quote_validity_seconds,
+        total_price,
+        expiry,
         quote_id,
-    }))
+        now,
+    })
 }
 
-/// x402 Challenge - Returns 402 Payment Required with headers
-/// This is the core of the x402-RWA protocol
-pub async fn buy_challenge(
+/// Get a quote for an asset purchase
+pub async fn get_quote(
     State(state): State<AppState>,
     Path(asset): Path<String>,
     Query(query): Query<QuoteQuery>,
-) -> Result<impl IntoResponse, AppError> {
-    let asset_info = state
-        .blockchain
-        .get_asset(&asset)
-        .await?
-        .ok_or_else(|| AppError::AssetNotFound(asset.clone()))?;
+) -> Result<Json<Quote>, AppError> {
+    let quote = issue_quote(&state, &asset, &query).await?;
     
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .unwrap()
-        .as_secs();
+    Ok(Json(Quote {
+        asset_id: quote.asset,
+        amount: quote.amount,
+        price_per_unit: quote.asset_info.price_per_unit,
+        total_price: quote.total_price,
+        fee_bps: quote.fee_bps,
+        fee: quote.fee,
+        expiry: quote.expiry,
+        expiry_iso: chrono::DateTime::from_timestamp(quote.expiry as i64, 0)
+            .map(|t| t.to_rfc3339())
+            .unwrap_or_default(),
+        expires_in_seconds: quote.expiry.saturating_sub(quote.now),
+        quote_id: quote.quote_id,
+        remainder_usdc: quote.remainder_usdc,
+    }))
+}
+
+/// x402-RWA protocol versions this server speaks, oldest first
+pub const SUPPORTED_VERSIONS: &[u32] = &[1];
+
+/// Media type agents name in `Accept` to negotiate a protocol version
+const X402_MEDIA_TYPE: &str = "application/x402-rwa+json";
+
+/// Pick the protocol version for a challenge from the `Accept` header
+///
+/// An agent asks for versions with entries like
+/// `application/x402-rwa+json; version=1`, and gets the highest one both
+/// sides support. Without such an entry (no `Accept`, `*/*`,
+/// `application/json`) it gets the latest version; naming the media type
+/// only with unsupported versions is a 406.
+fn negotiate_version(headers: &HeaderMap) -> Result<u32, AppError> {
+    let latest = *SUPPORTED_VERSIONS.last().unwrap();
+    let accept = headers
+        .get_all(header::ACCEPT)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','));
     
-    let total_price = query.amount * asset_info.price_per_unit;
-    let fee = total_price * 5 / 10_000;
-    let expiry = now + state.config.quote_validity_seconds;
+    let mut named = false;
+    let mut requested = Vec::new();
+    for entry in accept {
+        let mut parts = entry.split(';').map(str::trim);
+        if !parts.next().unwrap_or("").eq_ignore_ascii_case(X402_MEDIA_TYPE) {
+            continue;
+        }
+        named = true;
+        let mut version = None;
+        let mut refused = false;
+        for param in parts {
+            let Some((key, value)) = param.split_once('=') else { continue };
+            let value = value.trim().trim_matches('"');
+            match key.trim().to_ascii_lowercase().as_str() {
+                "version" => version = Some(value.parse::<u32>().ok()),
+                "q" => refused = value.parse::<f32>().is_ok_and(|q| q == 0.0),
+                _ => {}
+            }
+        }
+        if refused {
+            continue;
+        }
+        match version {
+            // No version parameter: any version of the media type will do
+            None => requested.push(latest),
+            Some(Some(version)) => requested.push(version),
+            Some(None) => {}
+        }
+    }
     
-    let quote_id = format!(
-        "{:016x}",
-        now ^ (query.amount << 32) ^ state.config.chain_id
-    );
+    if !named {
+        return Ok(latest);
+    }
+    
+    requested
+        .into_iter()
+        .filter(|version| SUPPORTED_VERSIONS.contains(version))
+        .max()
+        .ok_or_else(|| {
+            let supported: Vec<String> = SUPPORTED_VERSIONS.iter().map(u32::to_string).collect();
+            AppError::NotAcceptable(format!(
+                "no supported {} version requested (supported: {})",
+                X402_MEDIA_TYPE,
+                supported.join(", ")
+            ))
+        })
+}
+
+/// Build the x402 challenge for an issued quote
+///
+/// The body is the single source of truth: every `X-402-*` header (and the
+/// quote signature) is derived from it, so headers and body cannot disagree.
+fn build_x402_challenge(
+    state: &AppState,
+    quote: &IssuedQuote,
+    version: u32,
+) -> Result<(HeaderMap, X402Challenge), AppError> {
+    let challenge = X402Challenge {
+        error: "Payment Required".to_string(),
+        message: "Submit ZK compliance proof and payment to complete purchase".to_string(),
+        protocol: format!("x402-RWA/{}.0", version),
+        asset: quote.asset.clone(),
+        asset_address: quote.asset_info.address.clone(),
+        amount: quote.amount,
+        base_price: quote.base_price,
+        fee: quote.fee,
+        total_price: quote.total_price,
+        currency: "USDC".to_string(),
+        expiry: quote.expiry,
+        quote_id: quote.quote_id.clone(),
+        compliance_circuit: quote.asset_info.compliance_circuit.clone(),
+        payment_address: state.config.clearinghouse_address.clone(),
+        chain_id: state.config.chain_id,
+        remainder_usdc: quote.remainder_usdc,
+    };
     
-    // Build x402 response headers
     let mut headers = HeaderMap::new();
-    headers.insert("X-402-Asset-ID", asset.parse().unwrap());
-    headers.insert("X-402-Price", (total_price + fee).to_string().parse().unwrap());
-    headers.insert("X-402-Currency", "USDC-BASE".parse().unwrap());
-    headers.insert("X-402-Compliance-Circuit", asset_info.compliance_circuit.parse().unwrap());
-    headers.insert("X-402-Payment-Address", state.config.clearinghouse_address.parse().unwrap());
-    headers.insert("X-402-Expiry", expiry.to_string().parse().unwrap());
-    headers.insert("X-402-Quote-ID", quote_id.parse().unwrap());
-    headers.insert("X-402-Chain-ID", state.config.chain_id.to_string().parse().unwrap());
-    headers.insert("X-402-Asset-Address", asset_info.address.parse().unwrap());
+    headers.insert("X-402-Version", version.to_string().parse().unwrap());
+    headers.insert("X-402-Asset-ID", challenge.asset.parse().unwrap());
+    headers.insert("X-402-Price", challenge.total_price.to_string().parse().unwrap());
+    headers.insert("X-402-Base-Price", challenge.base_price.to_string().parse().unwrap());
+    headers.insert("X-402-Fee", challenge.fee.to_string().parse().unwrap());
+    headers.insert("X-402-Currency", challenge.currency.parse().unwrap());
+    headers.insert("X-402-Compliance-Circuit", challenge.compliance_circuit.parse().unwrap());
+    headers.insert("X-402-Payment-Address", challenge.payment_address.parse().unwrap());
+    headers.insert("X-402-Expiry", challenge.expiry.to_string().parse().unwrap());
+    headers.insert("X-402-Quote-ID", challenge.quote_id.parse().unwrap());
+    headers.insert("X-402-Chain-ID", challenge.chain_id.to_string().parse().unwrap());
+    headers.insert("X-402-Asset-Address", challenge.asset_address.parse().unwrap());
     headers.insert(
         header::WWW_AUTHENTICATE,
-        "Token x402-RWA".parse().unwrap(),
+        www_authenticate(&challenge).parse().unwrap(),
     );
     
-    let body = serde_json::json!({
-        "error": "Payment Required",
-        "message": "Submit ZK compliance proof and payment to complete purchase",
-        "protocol": "x402-RWA/1.0",
-        "asset": asset,
-        "amount": query.amount,
-        "total_price": total_price + fee,
-        "currency": "USDC",
-        "expiry": expiry,
-        "quote_id": quote_id,
-        "compliance_circuit": asset_info.compliance_circuit,
-        "payment_address": state.config.clearinghouse_address,
-    });
+    // Sign the quote so agents can detect tampering in transit
+    if let Some(signer) = &state.quote_signer {
+        let payment_address = challenge
+            .payment_address
+            .parse()
+            .map_err(|_| AppError::Internal("Invalid clearinghouse address".to_string()))?;
+        let digest = auth::quote_digest(
+            &challenge.asset,
+            challenge.amount,
+            challenge.total_price,
+            payment_address,
+            challenge.expiry,
+            &challenge.quote_id,
+            challenge.chain_id,
+        );
+        headers.insert("X-402-Signature", auth::sign_digest(signer, &digest)?.parse().unwrap());
+    }
     
-    Ok((StatusCode::PAYMENT_REQUIRED, headers, Json(body)))
+    Ok((headers, challenge))
 }
 
-/// Execute a buy after receiving proof + payment
-pub async fn execute_buy(
+/// `WWW-Authenticate` challenge in the `x402-RWA` scheme
+///
+/// Tells the agent which circuit to prove, where to submit the settlement,
+/// and where a proof can be checked first, e.g.
+/// `x402-RWA realm="402-clearinghouse", circuit="0x…", quote_id="…",
+/// submit="/api/v1/trade/buy/TBILL-26", verify="/api/v1/verify"`.
+fn www_authenticate(challenge: &X402Challenge) -> String {
+    // Quoted-string per RFC 9110
+    fn quoted(value: &str) -> String {
+        format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\""))
+    }
+    
+    let params = [
+        ("realm", "402-clearinghouse".to_string()),
+        ("circuit", challenge.compliance_circuit.clone()),
+        ("quote_id", challenge.quote_id.clone()),
+        ("submit", format!("/api/v1/trade/buy/{}", challenge.asset)),
+        ("verify", "/api/v1/verify".to_string()),
+    ];
+    let params: Vec<String> = params
+        .iter()
+        .map(|(key, value)| format!("{}={}", key, quoted(value)))
+        .collect();
+    format!("x402-RWA {}", params.join(", "))
+}
+
+/// x402 Challenge - Returns 402 Payment Required with headers
+/// This is the core of the x402-RWA protocol
+pub async fn buy_challenge(
     State(state): State<AppState>,
     Path(asset): Path<String>,
-    Json(request): Json<SettlementRequest>,
-) -> Result<Json<SettlementResponse>, AppError> {
+    Query(query): Query<QuoteQuery>,
+    request_headers: HeaderMap,
+) -> Result<impl IntoResponse, AppError> {
+    // Negotiate before issuing, so a 406 doesn't leave a quote behind
+    let version = negotiate_version(&request_headers)?;
+    let quote = issue_quote(&state, &asset, &query).await?;
+    let (headers, challenge) = build_x402_challenge(&state, &quote, version)?;
+    
+    Ok((StatusCode::PAYMENT_REQUIRED, headers, Json(challenge)))
+}
+
+/// A settlement request that passed every off-chain check
+struct PreparedSettlement {
+    asset_info: Asset,
+    quote: StoredQuote,
+    compliance_proof: Vec<u8>,
+    public_values: Vec<u8>,
+    now: u64,
+}
+
+/// Run the checks shared by `execute_buy` and `simulate_buy`
+async fn prepare_settlement(
+    state: &AppState,
+    asset: &str,
+    request: &SettlementRequest,
+) -> Result<PreparedSettlement, AppError> {
     // Validate asset exists
     let asset_info = state
         .blockchain
-        .get_asset(&asset)
+        .get_asset(asset)
         .await?
-        .ok_or_else(|| AppError::AssetNotFound(asset.clone()))?;
+        .ok_or_else(|| AppError::AssetNotFound(asset.to_string()))?;
     
     // Validate quote hasn't expired
     let now = SystemTime::now()
@@ -183,25 +557,104 @@ pub async fn execute_buy(
         .unwrap()
         .as_secs();
     
-    // In production: verify quote_id matches and hasn't been used
+    // Hold the settlement to the terms quoted for this quote_id
+    let quote = state
+        .stores
+        .quotes
+        .get(&request.quote_id)
+        .ok_or_else(|| AppError::BadRequest(format!("Unknown quote: {}", request.quote_id)))?;
+    if quote.expiry < now {
+        return Err(AppError::QuoteExpired);
+    }
+    let requested_asset = state
+        .blockchain
+        .get_asset(&request.asset)
+        .await?
+        .map(|a| a.id);
+    if quote.asset_id != asset_info.id
+        || requested_asset.as_deref() != Some(quote.asset_id.as_str())
+        || quote.amount != request.amount
+    {
+        return Err(AppError::BadRequest("quote mismatch".to_string()));
+    }
+    
+    // In production: verify the quote hasn't been used
+    
+    // The asset's circuit must have a registered verification key
+    state.vk_registry.get(&asset_info.compliance_circuit)?;
     
     // Decode proofs
-    let compliance_proof = hex::decode(&request.compliance_proof.trim_start_matches("0x"))
-        .map_err(|e| AppError::BadRequest(format!("Invalid proof encoding: {}", e)))?;
+    let compliance_proof = decode_hex_field(&request.compliance_proof, MAX_PROOF_BYTES)
+        .map_err(AppError::InvalidProofEncoding)?;
+    
+    let public_values = decode_hex_field(&request.public_values, MAX_PUBLIC_VALUES_BYTES)
+        .map_err(AppError::InvalidPublicValuesEncoding)?;
+    
+    // A proof generated too far from now (clock skew aside) is stale or pre-dated
+    state.verifier.check_freshness(&public_values)?;
     
-    let public_values = hex::decode(&request.public_values.trim_start_matches("0x"))
-        .map_err(|e| AppError::BadRequest(format!("Invalid public values: {}", e)))?;
+    // Authenticate: the request must be signed by the agent the proof is bound to
+    let digest = auth::settlement_digest(
+        &request.asset,
+        request.amount,
+        &request.quote_id,
+        &compliance_proof,
+        &public_values,
+    );
+    let agent = auth::verify_agent_signature(&digest, &request.request_signature, &public_values)?;
     
-    // Calculate expiry based on quote (in production, track quote expiry properly)
-    let quote_expiry = now + 60; // 1 minute grace period
+    // Preflight funding: settle() pulls the full price, so a shortfall is a guaranteed revert.
+    // A permit grants the allowance in the same transaction, so skip the check then.
+    if request.payment_signature.is_none() {
+        let total_price = quote.total_price;
+        let (balance, allowance) = state
+            .blockchain
+            .check_agent_funding(&format!("{:?}", agent))
+            .await?;
+        if balance < total_price {
+            return Err(AppError::InsufficientBalance {
+                required: total_price,
+                shortfall: total_price - balance,
+            });
+        }
+        if allowance < total_price {
+            return Err(AppError::InsufficientAllowance {
+                required: total_price,
+                shortfall: total_price - allowance,
+            });
+        }
+    }
+    
+    Ok(PreparedSettlement {
+        asset_info,
+        quote,
+        compliance_proof,
+        public_values,
+        now,
+    })
+}
+
+/// Execute a buy after receiving proof + payment
+pub async fn execute_buy(
+    State(state): State<AppState>,
+    Path(asset): Path<String>,
+    Json(request): Json<SettlementRequest>,
+) -> Result<Json<SettlementResponse>, AppError> {
+    let PreparedSettlement {
+        asset_info,
+        quote,
+        compliance_proof,
+        public_values,
+        now,
+    } = prepare_settlement(&state, &asset, &request).await?;
     
     // Execute on-chain settlement
-    let tx_hash = state
+    let receipt = state
         .blockchain
         .execute_settlement(
             &asset_info.address,
             request.amount,
-            quote_expiry,
+            quote.expiry,
             &compliance_proof,
             &public_values,
         )
@@ -209,25 +662,222 @@ pub async fn execute_buy(
     
     let settlement_id = format!("{:016x}", now ^ request.amount);
     
+    let confirmations = wait_for_confirmations(&state, receipt.block_number).await?;
+    let status = if confirmations >= state.config.confirmations {
+        SettlementStatus::Settled
+    } else {
+        SettlementStatus::Pending
+    };
+    
     Ok(Json(SettlementResponse {
-        status: SettlementStatus::Settled,
-        tx_hash: Some(tx_hash),
+        status,
+        tx_hash: Some(receipt.tx_hash),
         asset_delivered: asset,
         amount: request.amount,
         settlement_id,
         timestamp: now,
+        block_number: receipt.block_number,
+        confirmations,
+    }))
+}
+
+/// Dry-run a buy: the same checks as `execute_buy`, then an `eth_call` of
+/// `settle(...)` with a gas estimate. Nothing is sent on-chain.
+pub async fn simulate_buy(
+    State(state): State<AppState>,
+    Path(asset): Path<String>,
+    Json(request): Json<SettlementRequest>,
+) -> Result<Json<SimulationResponse>, AppError> {
+    let prepared = prepare_settlement(&state, &asset, &request).await?;
+    
+    let simulation = state
+        .blockchain
+        .simulate_settlement(
+            &prepared.asset_info.address,
+            request.amount,
+            prepared.quote.expiry,
+            &prepared.compliance_proof,
+            &prepared.public_values,
+        )
+        .await?;
+    
+    Ok(Json(SimulationResponse {
+        would_succeed: simulation.revert_reason.is_none(),
+        gas_estimate: simulation.gas_estimate,
+        revert_reason: simulation.revert_reason,
+        total_price: prepared.quote.total_price,
     }))
 }
 
+/// How long `execute_buy` waits for confirmations before answering `pending`
+const CONFIRMATION_TIMEOUT: Duration = Duration::from_secs(30);
+const CONFIRMATION_POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// Confirmations of a block, counting the block itself
+async fn confirmations_of(state: &AppState, block_number: u64) -> Result<u64, AppError> {
+    let head = state.blockchain.get_block_number().await?;
+    Ok((head + 1).saturating_sub(block_number))
+}
+
+/// Wait (bounded) for a settlement to reach the configured depth
+async fn wait_for_confirmations(state: &AppState, block_number: u64) -> Result<u64, AppError> {
+    let deadline = tokio::time::Instant::now() + CONFIRMATION_TIMEOUT;
+    loop {
+        let confirmations = confirmations_of(state, block_number).await?;
+        if confirmations >= state.config.confirmations
+            || tokio::time::Instant::now() + CONFIRMATION_POLL_INTERVAL > deadline
+        {
+            return Ok(confirmations);
+        }
+        tokio::time::sleep(CONFIRMATION_POLL_INTERVAL).await;
+    }
+}
+
+/// Poll a settlement until it reaches the configured confirmation depth
+pub async fn settlement_status(
+    State(state): State<AppState>,
+    Path(tx_hash): Path<String>,
+) -> Result<Json<SettlementStatusResponse>, AppError> {
+    let block_number = state
+        .blockchain
+        .get_transaction_block(&tx_hash)
+        .await?
+        .ok_or_else(|| AppError::SettlementNotFound(tx_hash.clone()))?;
+    
+    let confirmations = confirmations_of(&state, block_number).await?;
+    let status = if confirmations >= state.config.confirmations {
+        SettlementStatus::Settled
+    } else {
+        SettlementStatus::Pending
+    };
+    
+    Ok(Json(SettlementStatusResponse {
+        tx_hash,
+        status,
+        block_number,
+        confirmations,
+        required_confirmations: state.config.confirmations,
+    }))
+}
+
+/// Check a compliance proof's public values without settling
+///
+/// The proof bytes are only decoded; the contract verifies them at settlement.
+pub async fn verify_proof(
+    State(state): State<AppState>,
+    Json(request): Json<VerifyRequest>,
+) -> Result<Json<VerifyResponse>, AppError> {
+    let proof = decode_hex_field(&request.proof, MAX_PROOF_BYTES)
+        .map_err(AppError::InvalidProofEncoding)?;
+    
+    let public_values = decode_hex_field(&request.public_values, MAX_PUBLIC_VALUES_BYTES)
+        .map_err(AppError::InvalidPublicValuesEncoding)?;
+    
+    let response = match state
+        .verifier
+        .verify(&request.circuit_id, &proof, &public_values)?
+    {
+        Verification::Unchecked(committed) => VerifyResponse {
+            public_values_ok: true,
+            reason: None,
+            committed: Some(committed),
+        },
+        Verification::Rejected(reason) => VerifyResponse {
+            public_values_ok: false,
+            reason: Some(reason.to_string()),
+            committed: None,
+        },
+    };
+    
+    Ok(Json(response))
+}
+
+/// Largest accepted proof, comfortably above an SP1 PLONK proof (~870 bytes)
+const MAX_PROOF_BYTES: usize = 2048;
+
+/// Largest accepted public values; the circuits commit at most four words
+const MAX_PUBLIC_VALUES_BYTES: usize = 256;
+
+/// Decode a `0x`-prefixed hex field of at most `max_bytes`, describing
+/// exactly what is wrong with it
+///
+/// Positions are character offsets into the submitted string, prefix included.
+fn decode_hex_field(value: &str, max_bytes: usize) -> Result<Vec<u8>, String> {
+    let digits = value.strip_prefix("0x").unwrap_or(value);
+    let offset = value.len() - digits.len();
+    
+    if digits.is_empty() {
+        return Err("empty".to_string());
+    }
+    if digits.len() > max_bytes * 2 {
+        return Err(format!("too long ({} hex digits, max {} bytes)", digits.len(), max_bytes));
+    }
+    if !digits.len().is_multiple_of(2) {
+        return Err(format!("odd number of hex digits ({})", digits.len()));
+    }
+    
+    hex::decode(digits).map_err(|e| match e {
+        hex::FromHexError::InvalidHexCharacter { c, index } => {
+            format!("invalid character {:?} at position {}", c, index + offset)
+        }
+        other => other.to_string(),
+    })
+}
+
 /// Check agent verification status
 pub async fn agent_status(
     State(state): State<AppState>,
     Path(address): Path<String>,
 ) -> Result<Json<AgentStatus>, AppError> {
-    let status = state.blockchain.get_agent_status(&address).await?;
+    let mut status = state.blockchain.get_agent_status(&address).await?;
+    
+    let totals = state.indexer.agent_totals(&address);
+    status.total_settlements = totals.settlements;
+    status.total_volume_usdc = totals.volume_usdc;
+    
     Ok(Json(status))
 }
 
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    pub agent: Option<String>,
+}
+
+/// Live feed of settlements seen by the indexer (Server-Sent Events)
+///
+/// Reconnecting clients send `Last-Event-ID` to replay what they missed, as
+/// far back as the indexer's recent buffer reaches.
+pub async fn settlement_events(
+    State(state): State<AppState>,
+    Query(query): Query<EventsQuery>,
+    headers: HeaderMap,
+) -> Sse<impl Stream<Item = Result<Event, Infallible>>> {
+    let last_event_id = headers
+        .get("Last-Event-ID")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok());
+    let agent = query.agent.map(|a| a.to_lowercase());
+    
+    let (replay, receiver) = state.indexer.subscribe(last_event_id);
+    
+    // A lagging client skips the events it missed rather than disconnecting
+    let live = BroadcastStream::new(receiver).filter_map(Result::ok);
+    let stream = tokio_stream::iter(replay)
+        .chain(live)
+        .filter(move |event| agent.as_ref().is_none_or(|a| &event.log.agent == a))
+        .map(|event| Ok(settlement_event(&event)));
+    
+    Sse::new(stream).keep_alive(KeepAlive::default())
+}
+
+fn settlement_event(event: &SettlementEvent) -> Event {
+    Event::default()
+        .event("settlement")
+        .id(event.id.to_string())
+        .json_data(event)
+        .unwrap_or_else(|_| Event::default().comment("unserializable settlement"))
+}
+
 /// Get compliance circuit details for an asset
 pub async fn get_compliance_circuit(
     State(state): State<AppState>,
@@ -246,6 +896,10 @@ pub async fn get_compliance_circuit(
         description: "Proves the operator meets SEC accredited investor criteria without revealing identity".to_string(),
         ipfs_hash: format!("ipfs://Qm{}", &asset_info.compliance_circuit[..40]),
         verifier_address: state.config.clearinghouse_address.clone(),
+        vk_hash: state
+            .vk_registry
+            .find(&asset_info.compliance_circuit)
+            .and_then(|vk| vk.hash.clone()),
         required_claims: vec![
             "accredited_investor".to_string(),
             "not_sanctioned".to_string(),
@@ -254,5 +908,323 @@ pub async fn get_compliance_circuit(
     }))
 }
 
+/// Reject admin requests without the configured `Authorization: Bearer` key
+fn require_admin(state: &AppState, headers: &HeaderMap) -> Result<(), AppError> {
+    let expected = state
+        .config
+        .admin_api_key
+        .as_deref()
+        .ok_or_else(|| AppError::Unauthorized("Admin API disabled".to_string()))?;
+    
+    let provided = headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    
+    // Compare digests in constant time so response timing can't leak the key
+    let matches = provided.is_some_and(|provided| {
+        let (provided, expected) = (sha2::Sha256::digest(provided), sha2::Sha256::digest(expected));
+        provided.iter().zip(expected.iter()).fold(0u8, |diff, (a, b)| diff | (a ^ b)) == 0
+    });
+    if !matches {
+        return Err(AppError::Unauthorized("Invalid admin key".to_string()));
+    }
+    Ok(())
+}
+
+/// List a new asset (admin)
+pub async fn admin_add_asset(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Json(asset): Json<Asset>,
+) -> Result<(StatusCode, Json<Asset>), AppError> {
+    require_admin(&state, &headers)?;
+    
+    // Otherwise every settlement for the asset would fail on the missing key
+    if state.vk_registry.find(&asset.compliance_circuit).is_none() {
+        return Err(AppError::BadRequest(format!(
+            "No verification key registered for compliance circuit {}",
+            asset.compliance_circuit
+        )));
+    }
+    state.registry.insert(asset.clone())?;
+    tracing::info!("Admin listed asset {} at {}", asset.id, asset.address);
+    
+    Ok((StatusCode::CREATED, Json(asset)))
+}
+
+/// Delist an asset (admin)
+pub async fn admin_remove_asset(
+    State(state): State<AppState>,
+    headers: HeaderMap,
+    Path(asset): Path<String>,
+) -> Result<StatusCode, AppError> {
+    require_admin(&state, &headers)?;
+    
+    state
+        .registry
+        .remove(&asset)
+        .ok_or_else(|| AppError::AssetNotFound(asset.clone()))?;
+    tracing::info!("Admin delisted asset {}", asset);
+    
+    Ok(StatusCode::NO_CONTENT)
+}
+
 // Re-export for sha2 usage
 use sha2::Digest;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::registry::InMemoryAssetRegistry;
+
+    fn tbill() -> Asset {
+        InMemoryAssetRegistry::with_defaults().list().remove(0)
+    }
+
+    #[test]
+    fn subtotal_prices_whole_units() {
+        assert_eq!(quote_subtotal(5, &tbill()).unwrap(), 4_900_000);
+    }
+
+    #[test]
+    fn subtotal_rejects_unsettleable_amounts() {
+        let bounded = Asset { min_amount: Some(10), max_amount: Some(20), ..tbill() };
+        let unpriced = Asset { price_per_unit: 0, ..tbill() };
+        let expensive = Asset { price_per_unit: u64::MAX, ..tbill() };
+        for (amount, asset) in [
+            (0, tbill()),
+            (MAX_QUOTE_AMOUNT + 1, tbill()),
+            (9, bounded.clone()),
+            (21, bounded),
+            (1, unpriced),
+            (2, expensive),
+        ] {
+            assert!(
+                matches!(quote_subtotal(amount, &asset), Err(AppError::BadRequest(_))),
+                "amount {} of {:?}",
+                amount,
+                asset
+            );
+        }
+    }
+
+    #[test]
+    fn value_query_buys_whole_units_and_reports_the_remainder() {
+        let query = QuoteQuery { amount: None, value_usdc: Some(2_500_000) };
+        assert_eq!(query.resolve(&tbill()).unwrap(), (2, Some(540_000)));
+
+        let query = QuoteQuery { amount: Some(3), value_usdc: None };
+        assert_eq!(query.resolve(&tbill()).unwrap(), (3, None));
+    }
+
+    #[test]
+    fn quote_query_needs_exactly_one_size_of_at_least_a_unit() {
+        for (amount, value_usdc) in [(None, None), (Some(1), Some(980_000)), (None, Some(979_999))] {
+            let query = QuoteQuery { amount, value_usdc };
+            assert!(matches!(query.resolve(&tbill()), Err(AppError::BadRequest(_))));
+        }
+    }
+
+    #[test]
+    fn fee_rounds_up_and_respects_the_floor() {
+        assert_eq!(compute_fee(4_900_000, 5, 0), 2_450);
+        assert_eq!(compute_fee(1, 5, 0), 1);
+        assert_eq!(compute_fee(0, 5, 0), 0);
+        assert_eq!(compute_fee(1_000, 5, 10_000), 10_000);
+        assert_eq!(compute_fee(u64::MAX, 10_000, 0), u64::MAX);
+    }
+
+    async fn state(private_key: Option<&str>) -> AppState {
+        let mut config = Config::from_env().unwrap();
+        config.private_key = private_key.map(str::to_string);
+        let registry: Arc<dyn AssetRegistry> = Arc::new(InMemoryAssetRegistry::with_defaults());
+        let blockchain = BlockchainService::new(&config, registry.clone()).await.unwrap();
+        AppState::new(config, blockchain, registry).unwrap()
+    }
+
+    fn issued() -> IssuedQuote {
+        IssuedQuote {
+            asset: "TBILL-26".to_string(),
+            asset_info: tbill(),
+            amount: 5,
+            remainder_usdc: None,
+            base_price: 4_900_000,
+            fee_bps: 5,
+            fee: 2_450,
+            total_price: 4_902_450,
+            expiry: 1_700_000_100,
+            quote_id: "feed".to_string(),
+            now: 1_700_000_000,
+        }
+    }
+
+    #[tokio::test]
+    async fn challenge_is_signed_over_the_quote_terms() {
+        // Anvil/Hardhat account #0
+        let key = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+        let state = state(Some(key)).await;
+        let (headers, body) = build_x402_challenge(&state, &issued(), 1).unwrap();
+
+        let digest = auth::quote_digest(
+            &body.asset,
+            body.amount,
+            body.total_price,
+            body.payment_address.parse().unwrap(),
+            body.expiry,
+            &body.quote_id,
+            body.chain_id,
+        );
+        let signature = auth::sign_digest(state.quote_signer.as_ref().unwrap(), &digest).unwrap();
+        assert_eq!(headers.get("X-402-Signature").unwrap().to_str().unwrap(), signature);
+    }
+
+    #[tokio::test]
+    async fn challenge_is_unsigned_without_a_relay_key() {
+        let (headers, _) = build_x402_challenge(&state(None).await, &issued(), 1).unwrap();
+        assert!(headers.get("X-402-Signature").is_none());
+    }
+
+    #[tokio::test]
+    async fn challenge_headers_repeat_the_body() {
+        let state = state(None).await;
+        let (headers, body) = build_x402_challenge(&state, &issued(), 1).unwrap();
+
+        let header = |name: &str| headers.get(name).unwrap().to_str().unwrap().to_string();
+        assert_eq!(header("X-402-Asset-ID"), body.asset);
+        assert_eq!(header("X-402-Price"), body.total_price.to_string());
+        assert_eq!(header("X-402-Base-Price"), body.base_price.to_string());
+        assert_eq!(header("X-402-Fee"), body.fee.to_string());
+        assert_eq!(header("X-402-Currency"), body.currency);
+        assert_eq!(header("X-402-Compliance-Circuit"), body.compliance_circuit);
+        assert_eq!(header("X-402-Payment-Address"), body.payment_address);
+        assert_eq!(header("X-402-Expiry"), body.expiry.to_string());
+        assert_eq!(header("X-402-Quote-ID"), body.quote_id);
+        assert_eq!(header("X-402-Chain-ID"), body.chain_id.to_string());
+        assert_eq!(header("X-402-Asset-Address"), body.asset_address);
+        assert_eq!(body.total_price, body.base_price + body.fee);
+    }
+
+    #[test]
+    fn www_authenticate_quotes_its_parameters() {
+        let challenge = X402Challenge {
+            error: String::new(),
+            message: String::new(),
+            protocol: "x402-RWA/1.0".to_string(),
+            asset: "TBILL-26".to_string(),
+            asset_address: String::new(),
+            amount: 5,
+            base_price: 0,
+            fee: 0,
+            total_price: 0,
+            currency: "USDC".to_string(),
+            expiry: 0,
+            quote_id: "fe\"ed".to_string(),
+            compliance_circuit: "0xDd2ffa97F680032332EA4905586e2366584Ae0be".to_string(),
+            payment_address: String::new(),
+            chain_id: 8453,
+            remainder_usdc: None,
+        };
+        assert_eq!(
+            www_authenticate(&challenge),
+            "x402-RWA realm=\"402-clearinghouse\", circuit=\"0xDd2ffa97F680032332EA4905586e2366584Ae0be\", \
+             quote_id=\"fe\\\"ed\", submit=\"/api/v1/trade/buy/TBILL-26\", verify=\"/api/v1/verify\""
+        );
+    }
+
+    #[tokio::test]
+    async fn admin_key_must_match_exactly() {
+        let mut state = state(None).await;
+        state.config.admin_api_key = Some("s3cret".to_string());
+        let bearer = |value: &str| {
+            let mut headers = HeaderMap::new();
+            headers.insert(header::AUTHORIZATION, value.parse().unwrap());
+            headers
+        };
+
+        require_admin(&state, &bearer("Bearer s3cret")).unwrap();
+        for refused in ["Bearer s3cre", "Bearer s3cret2", "Bearer S3CRET", "s3cret", "Bearer "] {
+            assert!(matches!(require_admin(&state, &bearer(refused)), Err(AppError::Unauthorized(_))), "{}", refused);
+        }
+        assert!(require_admin(&state, &HeaderMap::new()).is_err());
+    }
+
+    #[test]
+    fn quote_id_is_stable_and_covers_every_input() {
+        let id = compute_quote_id("TBILL-26", 5, 1_700_000_100, 8453);
+        assert_eq!(id, compute_quote_id("TBILL-26", 5, 1_700_000_100, 8453));
+        assert_eq!(id.len(), 64);
+        for other in [
+            compute_quote_id("TBILL-27", 5, 1_700_000_100, 8453),
+            compute_quote_id("TBILL-26", 6, 1_700_000_100, 8453),
+            compute_quote_id("TBILL-26", 5, 1_700_000_400, 8453),
+            compute_quote_id("TBILL-26", 5, 1_700_000_100, 84532),
+        ] {
+            assert_ne!(id, other);
+        }
+    }
+
+    #[test]
+    fn quote_expiry_aligns_to_windows_with_time_left_to_act() {
+        assert_eq!(quote_expiry(1_000, 300), 1_200);
+        // Exactly MIN_QUOTE_VALIDITY_SECONDS left stays in the window
+        assert_eq!(quote_expiry(1_190, 300), 1_200);
+        // One second less moves to the next window
+        assert_eq!(quote_expiry(1_191, 300), 1_500);
+        assert_eq!(quote_expiry(1_199, 300), 1_500);
+        // Windows shorter than MIN_QUOTE_VALIDITY_SECONDS are widened to it
+        assert_eq!(quote_expiry(1_000, 0), 1_010);
+        assert_eq!(quote_expiry(1_001, 1), 1_020);
+    }
+
+    #[tokio::test]
+    async fn global_validity_is_clamped() {
+        for (configured, clamped) in [(0, MIN_QUOTE_VALIDITY_SECONDS), (86_400, MAX_QUOTE_VALIDITY_SECONDS), (300, 300)] {
+            let mut state = state(None).await;
+            state.config.quote_validity_seconds = configured;
+            assert_eq!(quote_validity(&state, &tbill()), clamped, "{}", configured);
+        }
+    }
+
+    #[tokio::test]
+    async fn asset_validity_overrides_the_global_one_within_bounds() {
+        let state = state(None).await;
+        let with_validity = |secs| Asset { quote_validity_seconds: Some(secs), ..tbill() };
+        assert_eq!(quote_validity(&state, &tbill()), state.config.quote_validity_seconds);
+        assert_eq!(quote_validity(&state, &with_validity(30)), 30);
+        assert_eq!(quote_validity(&state, &with_validity(5)), MIN_QUOTE_VALIDITY_SECONDS);
+        assert_eq!(quote_validity(&state, &with_validity(86_400)), MAX_QUOTE_VALIDITY_SECONDS);
+    }
+
+    #[test]
+    fn hex_field_errors_describe_the_problem() {
+        assert_eq!(decode_hex_field("0x0a0B", 4).unwrap(), vec![0x0a, 0x0b]);
+        assert_eq!(decode_hex_field("0a0b", 4).unwrap(), vec![0x0a, 0x0b]);
+        assert_eq!(decode_hex_field("0x", 4).unwrap_err(), "empty");
+        assert_eq!(decode_hex_field("0x0a0b0c", 2).unwrap_err(), "too long (6 hex digits, max 2 bytes)");
+        assert_eq!(decode_hex_field("0xabc", 4).unwrap_err(), "odd number of hex digits (3)");
+        assert_eq!(decode_hex_field("0x0g", 4).unwrap_err(), "invalid character 'g' at position 3");
+    }
+
+    fn accept(value: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(header::ACCEPT, value.parse().unwrap());
+        headers
+    }
+
+    #[test]
+    fn version_negotiation() {
+        let latest = *SUPPORTED_VERSIONS.last().unwrap();
+        assert_eq!(negotiate_version(&HeaderMap::new()).unwrap(), latest);
+        assert_eq!(negotiate_version(&accept("*/*")).unwrap(), latest);
+        assert_eq!(negotiate_version(&accept("application/x402-rwa+json")).unwrap(), latest);
+        assert_eq!(
+            negotiate_version(&accept("application/x402-rwa+json; version=9, application/x402-rwa+json; version=1"))
+                .unwrap(),
+            1
+        );
+        for refused in ["application/x402-rwa+json; version=9", "application/x402-rwa+json; version=1; q=0"] {
+            assert!(matches!(negotiate_version(&accept(refused)), Err(AppError::NotAcceptable(_))));
+        }
+    }
+}
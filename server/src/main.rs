@@ -3,14 +3,14 @@
 //! An x402-compliant API gateway for agent-native RWA settlement.
 
 use axum::{
+    extract::DefaultBodyLimit,
     Router,
-    routing::{get, post},
+    routing::{delete, get, post},
 };
+use anyhow::Context;
+use axum_server::tls_rustls::RustlsConfig;
 use std::net::SocketAddr;
-use tower_http::{
-    cors::{Any, CorsLayer},
-    trace::TraceLayer,
-};
+use tower_http::trace::TraceLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 mod config;
@@ -20,8 +20,10 @@ mod middleware;
 mod models;
 mod services;
 
-use config::Config;
+use config::{Config, TlsConfig};
 use services::blockchain::BlockchainService;
+use services::registry::{AssetRegistry, InMemoryAssetRegistry};
+use std::sync::Arc;
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
@@ -41,13 +43,56 @@ async fn main() -> anyhow::Result<()> {
     tracing::info!("Starting 402 Clearinghouse Server");
     tracing::info!("Chain: Base Sepolia ({})", config.chain_id);
     tracing::info!("Clearinghouse: {}", config.clearinghouse_address);
+    tracing::info!("RPC: {} (USDC {})", config.rpc_url, config.usdc_address);
 
     // Initialize blockchain service
-    let blockchain = BlockchainService::new(&config).await?;
-    let state = handlers::AppState::new(config.clone(), blockchain);
+    let registry: Arc<dyn AssetRegistry> = Arc::new(InMemoryAssetRegistry::with_defaults());
+    let blockchain = BlockchainService::new(&config, registry.clone()).await?;
+    let state = handlers::AppState::new(config.clone(), blockchain, registry)?;
+    
+    // Follow Settlement events for agent statistics
+    state.indexer.clone().spawn(state.blockchain.clone());
+    
+    // Keep the chain head cached for health checks
+    state.chain_head.clone().spawn(state.blockchain.clone());
 
     // Build router
-    let app = Router::new()
+    let app = app(state)?;
+
+    // Start server
+    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
+    
+    if let Some(tls) = &config.tls {
+        // Fail at startup rather than on the first handshake
+        let rustls = load_tls(tls).await?;
+        
+        tracing::info!("Listening on {} (HTTPS)", addr);
+        axum_server::bind_rustls(addr, rustls)
+            .serve(app.into_make_service())
+            .await?;
+    } else {
+        tracing::info!("Listening on {}", addr);
+        let listener = tokio::net::TcpListener::bind(addr).await?;
+        axum::serve(listener, app).await?;
+    }
+
+    Ok(())
+}
+
+/// Certificate chain and key for HTTPS, checked before binding
+async fn load_tls(tls: &TlsConfig) -> anyhow::Result<RustlsConfig> {
+    // Several rustls crypto backends are linked in, so pick one explicitly
+    let _ = rustls::crypto::aws_lc_rs::default_provider().install_default();
+    
+    RustlsConfig::from_pem_file(&tls.cert_path, &tls.key_path)
+        .await
+        .with_context(|| format!("Failed to load TLS cert {} / key {}", tls.cert_path, tls.key_path))
+}
+
+/// Routes and middleware, without the listener
+fn app(state: handlers::AppState) -> anyhow::Result<Router> {
+    let config = state.config.clone();
+    Ok(Router::new()
         // Health check
         .route("/health", get(handlers::health))
         
@@ -55,6 +100,8 @@ async fn main() -> anyhow::Result<()> {
         .route("/api/v1/trade/quote/:asset", get(handlers::get_quote))
         .route("/api/v1/trade/buy/:asset", get(handlers::buy_challenge))
         .route("/api/v1/trade/buy/:asset", post(handlers::execute_buy))
+        .route("/api/v1/trade/simulate/:asset", post(handlers::simulate_buy))
+        .route("/api/v1/trade/settlement/:tx_hash", get(handlers::settlement_status))
         
         // Asset discovery
         .route("/api/v1/assets", get(handlers::list_assets))
@@ -63,27 +110,735 @@ async fn main() -> anyhow::Result<()> {
         // Agent verification status
         .route("/api/v1/agent/:address/status", get(handlers::agent_status))
         
+        // Live settlement feed
+        .route("/api/v1/events/settlements", get(handlers::settlement_events))
+        
         // Compliance circuit info
         .route("/api/v1/compliance/circuit/:asset", get(handlers::get_compliance_circuit))
+        .route("/api/v1/verify", post(handlers::verify_proof))
+        
+        // Asset administration (requires ADMIN_API_KEY)
+        .route("/api/v1/admin/assets", post(handlers::admin_add_asset))
+        .route("/api/v1/admin/assets/:asset", delete(handlers::admin_remove_asset))
         
         // State
         .with_state(state)
         
         // Middleware
+        .layer(DefaultBodyLimit::max(config.max_body_bytes))
+        .layer(axum::middleware::from_fn(middleware::require_json))
         .layer(TraceLayer::new_for_http())
-        .layer(
-            CorsLayer::new()
-                .allow_origin(Any)
-                .allow_methods(Any)
-                .allow_headers(Any),
+        .layer(axum::middleware::from_fn(middleware::request_id))
+        .layer(middleware::cors_layer(&config)?))
+}
+
+// Drive the mock chain, so they only build without `alloy`
+#[cfg(all(test, not(feature = "alloy")))]
+mod tests {
+    use super::*;
+    use axum::body::Body;
+    use axum::http::{header, HeaderMap, Method, Request, StatusCode};
+    use tower::ServiceExt;
+
+    /// State over the mock chain, seeded with the demo listing
+    async fn state_with(config: Config, setup: impl FnOnce(&mut BlockchainService)) -> handlers::AppState {
+        let registry: Arc<dyn AssetRegistry> = Arc::new(InMemoryAssetRegistry::with_defaults());
+        let mut blockchain = BlockchainService::new(&config, registry.clone()).await.unwrap();
+        setup(&mut blockchain);
+        handlers::AppState::new(config, blockchain, registry).unwrap()
+    }
+
+    async fn app_with(config: Config) -> Router {
+        app(state_with(config, |_| {}).await).unwrap()
+    }
+
+    fn config() -> Config {
+        Config::from_env().unwrap()
+    }
+
+    /// Status, headers and JSON body (`Null` when there is none)
+    async fn send(app: &Router, request: Request<Body>) -> (StatusCode, HeaderMap, serde_json::Value) {
+        let response = app.clone().oneshot(request).await.unwrap();
+        let (parts, body) = response.into_parts();
+        let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        let json = serde_json::from_slice(&bytes).unwrap_or(serde_json::Value::Null);
+        (parts.status, parts.headers, json)
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::get(uri).body(Body::empty()).unwrap()
+    }
+
+    /// As a real client sends it; `require_json` keys off `Content-Length`
+    fn post_json(uri: &str, body: &serde_json::Value) -> Request<Body> {
+        let body = body.to_string();
+        Request::post(uri)
+            .header(header::CONTENT_TYPE, "application/json")
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    fn from_origin(mut request: Request<Body>, origin: &str) -> Request<Body> {
+        request.headers_mut().insert(header::ORIGIN, origin.parse().unwrap());
+        request
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        let request = Request::builder()
+            .method(Method::OPTIONS)
+            .uri("/api/v1/assets")
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .body(Body::empty())
+            .unwrap();
+        from_origin(request, origin)
+    }
+
+    #[tokio::test]
+    async fn cors_reflects_only_listed_origins() {
+        let app = app_with(Config {
+            allowed_origins: vec!["https://agents.example".to_string()],
+            ..config()
+        })
+        .await;
+
+        let listed = "https://agents.example";
+        for request in [preflight(listed), from_origin(get("/api/v1/assets"), listed)] {
+            let (_, headers, _) = send(&app, request).await;
+            assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], listed);
+        }
+
+        let (_, headers, _) = send(&app, preflight("https://evil.example")).await;
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    #[tokio::test]
+    async fn cors_wildcard_allows_any_origin() {
+        let app = app_with(Config {
+            allowed_origins: vec!["*".to_string()],
+            ..config()
+        })
+        .await;
+
+        let (_, headers, _) = send(&app, preflight("https://anyone.example")).await;
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_ORIGIN], "*");
+    }
+
+    #[tokio::test]
+    async fn cors_without_an_allowlist_allows_no_origin() {
+        let app = app_with(config()).await;
+        let (_, headers, _) = send(&app, preflight("https://agents.example")).await;
+        assert!(headers.get(header::ACCESS_CONTROL_ALLOW_ORIGIN).is_none());
+    }
+
+    const ADMIN_KEY: &str = "test-admin-key";
+
+    fn with_admin_key(mut request: Request<Body>, key: &str) -> Request<Body> {
+        let bearer = format!("Bearer {}", key);
+        request.headers_mut().insert(header::AUTHORIZATION, bearer.parse().unwrap());
+        request
+    }
+
+    /// A second listing using the demo circuit, which has a registered key
+    fn gold() -> serde_json::Value {
+        serde_json::json!({
+            "id": "GOLD-1",
+            "name": "Tokenized Gold",
+            "symbol": "GOLD-1",
+            "address": "0x000000000000000000000000000000000000a11c",
+            "issuer": "0xc7554F1B16ad0b3Ce363d53364C9817743E32f90",
+            "price_per_unit": 2_000_000,
+            "currency": "USDC",
+            "compliance_circuit": "0xDd2ffa97F680032332EA4905586e2366584Ae0be",
+            "active": true
+        })
+    }
+
+    async fn listed_ids(app: &Router) -> Vec<String> {
+        let (_, _, assets) = send(app, get("/api/v1/assets")).await;
+        assets
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|asset| asset["id"].as_str().unwrap().to_string())
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn admin_endpoints_require_the_admin_key() {
+        let app = app_with(Config {
+            admin_api_key: Some(ADMIN_KEY.to_string()),
+            ..config()
+        })
+        .await;
+        let delete = || Request::delete("/api/v1/admin/assets/TBILL-26").body(Body::empty()).unwrap();
+
+        for request in [
+            post_json("/api/v1/admin/assets", &gold()),
+            with_admin_key(post_json("/api/v1/admin/assets", &gold()), "wrong-key"),
+            delete(),
+            with_admin_key(delete(), "wrong-key"),
+        ] {
+            let (status, _, _) = send(&app, request).await;
+            assert_eq!(status, StatusCode::UNAUTHORIZED);
+        }
+        assert_eq!(listed_ids(&app).await, ["TBILL-26"]);
+    }
+
+    #[tokio::test]
+    async fn admin_endpoints_are_disabled_without_a_configured_key() {
+        let app = app_with(config()).await;
+        let request = with_admin_key(post_json("/api/v1/admin/assets", &gold()), "");
+        let (status, _, _) = send(&app, request).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn admin_listing_and_delisting_changes_what_agents_see() {
+        let app = app_with(Config {
+            admin_api_key: Some(ADMIN_KEY.to_string()),
+            ..config()
+        })
+        .await;
+
+        let (status, _, _) = send(&app, with_admin_key(post_json("/api/v1/admin/assets", &gold()), ADMIN_KEY)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(listed_ids(&app).await, ["TBILL-26", "GOLD-1"]);
+        let (status, _, _) = send(&app, get("/api/v1/trade/quote/GOLD-1?amount=2")).await;
+        assert_eq!(status, StatusCode::OK);
+
+        let delete = Request::delete("/api/v1/admin/assets/GOLD-1").body(Body::empty()).unwrap();
+        let (status, _, _) = send(&app, with_admin_key(delete, ADMIN_KEY)).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        assert_eq!(listed_ids(&app).await, ["TBILL-26"]);
+        let (status, _, _) = send(&app, get("/api/v1/assets/GOLD-1")).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[tokio::test]
+    async fn admin_listing_needs_a_registered_verification_key() {
+        let app = app_with(Config {
+            admin_api_key: Some(ADMIN_KEY.to_string()),
+            ..config()
+        })
+        .await;
+        let mut asset = gold();
+        asset["compliance_circuit"] = format!("0x{}", "ab".repeat(32)).into();
+
+        let (status, _, body) = send(&app, with_admin_key(post_json("/api/v1/admin/assets", &asset), ADMIN_KEY)).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("No verification key registered"), "{}", body);
+        assert_eq!(listed_ids(&app).await, ["TBILL-26"]);
+    }
+
+    fn fixture_tls() -> TlsConfig {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/tls");
+        TlsConfig {
+            cert_path: format!("{}/cert.pem", dir),
+            key_path: format!("{}/key.pem", dir),
+        }
+    }
+
+    #[tokio::test]
+    async fn tls_load_fails_at_startup_on_a_bad_cert_or_key() {
+        let missing = TlsConfig {
+            cert_path: "/nonexistent/cert.pem".to_string(),
+            ..fixture_tls()
+        };
+        // A key where the certificate should be
+        let swapped = TlsConfig {
+            cert_path: fixture_tls().key_path,
+            ..fixture_tls()
+        };
+        for tls in [missing, swapped] {
+            assert!(load_tls(&tls).await.is_err(), "{:?}", tls);
+        }
+    }
+
+    #[tokio::test]
+    async fn tls_port_serves_https_and_refuses_plain_http() {
+        let rustls = load_tls(&fixture_tls()).await.unwrap();
+        let handle = axum_server::Handle::new();
+        let server = axum_server::bind_rustls(SocketAddr::from(([127, 0, 0, 1], 0)), rustls)
+            .handle(handle.clone())
+            .serve(app_with(config()).await.into_make_service());
+        tokio::spawn(server);
+        let port = handle.listening().await.unwrap().port();
+
+        // The fixture certificate is self-signed
+        let client = reqwest::Client::builder()
+            .danger_accept_invalid_certs(true)
+            .build()
+            .unwrap();
+        let https = client
+            .get(format!("https://localhost:{}/api/v1/assets", port))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(https.status(), reqwest::StatusCode::OK);
+
+        let plain = client.get(format!("http://localhost:{}/api/v1/assets", port)).send().await;
+        assert!(plain.is_err(), "plain http was answered: {:?}", plain.map(|r| r.status()));
+
+        handle.shutdown();
+    }
+
+    /// Anvil/Hardhat account #1, the key used by the agent CLI examples
+    const AGENT_KEY: &str = "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    /// Quote `amount` of TBILL-26, returning its id and total price
+    async fn quote(app: &Router, amount: u64) -> (String, u64) {
+        let (status, _, quote) = send(app, get(&format!("/api/v1/trade/quote/TBILL-26?amount={}", amount))).await;
+        assert_eq!(status, StatusCode::OK, "{}", quote);
+        (quote["quote_id"].as_str().unwrap().to_string(), quote["total_price"].as_u64().unwrap())
+    }
+
+    /// Settlement body for `amount` of `asset`, signed by the agent its proof is bound to
+    fn settlement(asset: &str, amount: u64, quote_id: &str) -> serde_json::Value {
+        use ethers::signers::{LocalWallet, Signer};
+
+        let agent: LocalWallet = AGENT_KEY.parse().unwrap();
+        let valid_until = std::time::SystemTime::now()
+            .duration_since(std::time::UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+            + 30 * 24 * 60 * 60;
+        let mut public_values = vec![0u8; 96];
+        public_values[12..32].copy_from_slice(agent.address().as_bytes());
+        public_values[56..64].copy_from_slice(&valid_until.to_be_bytes());
+        let proof = vec![0xab; 64];
+
+        let digest = services::auth::settlement_digest(asset, amount, quote_id, &proof, &public_values);
+        serde_json::json!({
+            "asset": asset,
+            "amount": amount,
+            "quote_id": quote_id,
+            "compliance_proof": format!("0x{}", hex::encode(&proof)),
+            "public_values": format!("0x{}", hex::encode(&public_values)),
+            "request_signature": services::auth::sign_digest(&agent, &digest).unwrap(),
+        })
+    }
+
+    fn buy(settlement: serde_json::Value) -> Request<Body> {
+        post_json("/api/v1/trade/buy/TBILL-26", &settlement)
+    }
+
+    async fn settled_count(state: &handlers::AppState) -> usize {
+        state.blockchain.get_settlement_logs(0, u64::MAX).await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn simulating_against_a_deactivated_asset_reports_the_revert() {
+        let state = state_with(config(), |_| {}).await;
+        let app = app(state.clone()).unwrap();
+        let (quote_id, _) = quote(&app, 5).await;
+
+        // Deactivated on-chain after quoting
+        let tbill = state.registry.remove("TBILL-26").unwrap();
+        state.registry.insert(models::Asset { active: false, ..tbill }).unwrap();
+
+        let request = post_json("/api/v1/trade/simulate/TBILL-26", &settlement("TBILL-26", 5, &quote_id));
+        let (status, _, simulation) = send(&app, request).await;
+        assert_eq!(status, StatusCode::OK, "{}", simulation);
+        assert_eq!(simulation["would_succeed"], false);
+        assert_eq!(simulation["revert_reason"], "InvalidAsset()");
+        assert!(simulation["gas_estimate"].is_null());
+        assert_eq!(settled_count(&state).await, 0);
+    }
+
+    #[tokio::test]
+    async fn quote_and_challenge_share_a_quote_id() {
+        let app = app_with(config()).await;
+        // Retried in case the two requests straddle a validity window
+        for _ in 0..2 {
+            let (_, _, quote) = send(&app, get("/api/v1/trade/quote/TBILL-26?amount=5")).await;
+            let (status, headers, challenge) = send(&app, get("/api/v1/trade/buy/TBILL-26?amount=5")).await;
+            assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+            if quote["expiry"] == challenge["expiry"] {
+                assert_eq!(quote["quote_id"], challenge["quote_id"]);
+                assert_eq!(quote["quote_id"].as_str(), headers["X-402-Quote-ID"].to_str().ok());
+                return;
+            }
+        }
+        panic!("quote and challenge never shared a validity window");
+    }
+
+    #[tokio::test]
+    async fn quote_fee_follows_the_contracts_fee_bps() {
+        let app = app(state_with(config(), |chain| chain.set_fee_bps(30)).await).unwrap();
+        let (status, _, quote) = send(&app, get("/api/v1/trade/quote/TBILL-26?amount=5")).await;
+        assert_eq!(status, StatusCode::OK, "{}", quote);
+        assert_eq!(quote["fee_bps"], 30);
+        // 0.30% of 5 x $0.98
+        assert_eq!(quote["fee"], 14_700);
+        assert_eq!(quote["total_price"], 4_914_700);
+
+        let (_, headers, _) = send(&app, get("/api/v1/trade/buy/TBILL-26?amount=5")).await;
+        assert_eq!(headers["X-402-Fee"], "14700");
+    }
+
+    #[tokio::test]
+    async fn quote_expiry_is_given_as_rfc3339_and_seconds_left() {
+        let unix_now = || {
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs()
+        };
+        let app = app_with(config()).await;
+
+        let before = unix_now();
+        let (_, _, quote) = send(&app, get("/api/v1/trade/quote/TBILL-26?amount=5")).await;
+        let after = unix_now();
+
+        let expiry = quote["expiry"].as_u64().unwrap();
+        let iso = chrono::DateTime::parse_from_rfc3339(quote["expiry_iso"].as_str().unwrap()).unwrap();
+        assert_eq!(iso.timestamp() as u64, expiry);
+        let left = quote["expires_in_seconds"].as_u64().unwrap();
+        assert!((expiry - after..=expiry - before).contains(&left), "{}", quote);
+    }
+
+    #[tokio::test]
+    async fn asset_quotes_expire_on_their_own_validity_window() {
+        let state = state_with(Config { quote_validity_seconds: 300, ..config() }, |_| {}).await;
+        let mut gold = gold();
+        gold["quote_validity_seconds"] = 30.into();
+        state.registry.insert(serde_json::from_value(gold).unwrap()).unwrap();
+        let app = app(state).unwrap();
+
+        for (asset, validity) in [("GOLD-1", 30), ("TBILL-26", 300)] {
+            let (_, _, quote) = send(&app, get(&format!("/api/v1/trade/quote/{}?amount=1", asset))).await;
+            let (expiry, left) = (quote["expiry"].as_u64().unwrap(), quote["expires_in_seconds"].as_u64().unwrap());
+            assert_eq!(expiry % validity, 0, "{} expires at the end of a {}s window", asset, validity);
+            assert!((10..validity + 10).contains(&left), "{}: {}s left", asset, left);
+        }
+    }
+
+    /// The first `count` SSE events of a response, as (id, data)
+    async fn sse_events(response: axum::response::Response, count: usize) -> Vec<(u64, serde_json::Value)> {
+        use tokio_stream::StreamExt;
+
+        let mut body = response.into_body().into_data_stream();
+        let (mut text, mut events) = (String::new(), Vec::new());
+        while events.len() < count {
+            let chunk = tokio::time::timeout(std::time::Duration::from_secs(5), body.next())
+                .await
+                .expect("no further event")
+                .unwrap()
+                .unwrap();
+            text.push_str(std::str::from_utf8(&chunk).unwrap());
+            while let Some(end) = text.find("\n\n") {
+                let event: String = text.drain(..end + 2).collect();
+                let field = |name: &str| event.lines().find_map(|line| line.strip_prefix(name)).map(str::trim);
+                // Keep-alive comments carry neither
+                if let (Some(id), Some(data)) = (field("id:"), field("data:")) {
+                    events.push((id.parse().unwrap(), serde_json::from_str(data).unwrap()));
+                }
+            }
+        }
+        events
+    }
+
+    #[tokio::test]
+    async fn settlements_are_streamed_to_subscribers() {
+        let state = state_with(config(), |_| {}).await;
+        let app = app(state.clone()).unwrap();
+        let stream = app.clone().oneshot(get("/api/v1/events/settlements")).await.unwrap();
+        assert_eq!(stream.headers()[header::CONTENT_TYPE], "text/event-stream");
+
+        let (quote_id, _) = quote(&app, 5).await;
+        let (status, _, receipt) = send(&app, buy(settlement("TBILL-26", 5, &quote_id))).await;
+        assert_eq!(status, StatusCode::OK, "{}", receipt);
+        state.indexer.poll(&state.blockchain).await.unwrap();
+
+        let events = sse_events(stream, 1).await;
+        assert_eq!(events[0].1["tx_hash"], receipt["tx_hash"]);
+        assert_eq!(events[0].1["amount"], 5);
+    }
+
+    #[tokio::test]
+    async fn reconnecting_replays_at_most_the_recent_buffer() {
+        let state = state_with(config(), |_| {}).await;
+        let app = app(state.clone()).unwrap();
+        let logs = (0..300)
+            .map(|i| services::indexer::SettlementLog {
+                block_number: 20_000_000,
+                log_index: i,
+                tx_hash: format!("0x{:064x}", i),
+                agent: "0x742d35cc6634c0532925a3b844bc9e7595f1ab23".to_string(),
+                asset: "TBILL-26".to_string(),
+                amount: 1,
+                price: 980_000,
+            })
+            .collect();
+        state.indexer.apply(20_000_000, logs);
+
+        let mut request = get("/api/v1/events/settlements");
+        request.headers_mut().insert("Last-Event-ID", "10".parse().unwrap());
+        let events = sse_events(app.clone().oneshot(request).await.unwrap(), 256).await;
+        // Events 0..=43 fell out of the 256-entry buffer, so replay starts at 44
+        let ids: Vec<u64> = events.iter().map(|(id, _)| *id).collect();
+        assert_eq!(ids, (44..300).collect::<Vec<_>>());
+
+        let mut request = get("/api/v1/events/settlements");
+        request.headers_mut().insert("Last-Event-ID", "297".parse().unwrap());
+        let events = sse_events(app.oneshot(request).await.unwrap(), 2).await;
+        assert_eq!((events[0].0, events[1].0), (298, 299));
+    }
+
+    #[tokio::test]
+    async fn funded_agent_settles() {
+        let state = state_with(config(), |_| {}).await;
+        let app = app(state.clone()).unwrap();
+        let (quote_id, _) = quote(&app, 5).await;
+
+        let (status, _, receipt) = send(&app, buy(settlement("TBILL-26", 5, &quote_id))).await;
+        assert_eq!(status, StatusCode::OK, "{}", receipt);
+        assert_eq!(receipt["status"], "settled");
+        assert_eq!(settled_count(&state).await, 1);
+    }
+
+    #[tokio::test]
+    async fn balance_shortfall_is_reported_before_anything_is_sent() {
+        let state = state_with(config(), |chain| chain.set_agent_funding(1_000_000, u64::MAX)).await;
+        let app = app(state.clone()).unwrap();
+        let (quote_id, total_price) = quote(&app, 5).await;
+
+        let (status, _, body) = send(&app, buy(settlement("TBILL-26", 5, &quote_id))).await;
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+        assert_eq!(
+            body["error"],
+            format!(
+                "Insufficient USDC balance: {} short of {} (atomic units)",
+                total_price - 1_000_000,
+                total_price
+            )
         );
+        assert_eq!(settled_count(&state).await, 0);
+    }
 
-    // Start server
-    let addr = SocketAddr::from(([0, 0, 0, 0], config.port));
-    tracing::info!("Listening on {}", addr);
-    
-    let listener = tokio::net::TcpListener::bind(addr).await?;
-    axum::serve(listener, app).await?;
+    #[tokio::test]
+    async fn allowance_shortfall_is_reported_separately() {
+        let state = state_with(config(), |chain| chain.set_agent_funding(u64::MAX, 2_000_000)).await;
+        let app = app(state.clone()).unwrap();
+        let (quote_id, total_price) = quote(&app, 5).await;
 
-    Ok(())
+        let (status, _, body) = send(&app, buy(settlement("TBILL-26", 5, &quote_id))).await;
+        assert_eq!(status, StatusCode::PAYMENT_REQUIRED);
+        assert_eq!(
+            body["error"],
+            format!(
+                "Insufficient USDC allowance for the clearinghouse: {} short of {} (atomic units)",
+                total_price - 2_000_000,
+                total_price
+            )
+        );
+        assert_eq!(settled_count(&state).await, 0);
+    }
+
+    #[tokio::test]
+    async fn permit_skips_the_allowance_preflight() {
+        let state = state_with(config(), |chain| chain.set_agent_funding(0, 0)).await;
+        let app = app(state.clone()).unwrap();
+        let (quote_id, _) = quote(&app, 5).await;
+
+        let mut body = settlement("TBILL-26", 5, &quote_id);
+        body["payment_signature"] = "0x01".into();
+        let (status, _, receipt) = send(&app, buy(body)).await;
+        assert_eq!(status, StatusCode::OK, "{}", receipt);
+        assert_eq!(settled_count(&state).await, 1);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn settlement_stays_pending_until_the_required_depth() {
+        // More blocks than the mock chain mines during execute_buy's bounded wait
+        let app = app_with(Config { confirmations: 30, ..config() }).await;
+        let (quote_id, _) = quote(&app, 5).await;
+
+        let (status, _, receipt) = send(&app, buy(settlement("TBILL-26", 5, &quote_id))).await;
+        assert_eq!(status, StatusCode::OK, "{}", receipt);
+        assert_eq!(receipt["status"], "pending");
+        assert!(receipt["confirmations"].as_u64().unwrap() < 30);
+
+        let status_uri = format!("/api/v1/trade/settlement/{}", receipt["tx_hash"].as_str().unwrap());
+        let (_, _, polled) = send(&app, get(&status_uri)).await;
+        assert_eq!(polled["status"], "pending");
+        assert_eq!(polled["required_confirmations"], 30);
+
+        // The mock chain mines a block every two seconds
+        tokio::time::advance(std::time::Duration::from_secs(60)).await;
+        let (_, _, polled) = send(&app, get(&status_uri)).await;
+        assert_eq!(polled["status"], "settled", "{}", polled);
+        assert!(polled["confirmations"].as_u64().unwrap() >= 30);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn stalled_backend_answers_with_an_rpc_timeout() {
+        let state = state_with(Config { rpc_timeout_secs: 2, ..config() }, |chain| {
+            chain.set_stall(std::time::Duration::from_secs(60));
+        })
+        .await;
+        let app = app(state).unwrap();
+
+        let (status, _, body) = send(&app, get("/api/v1/trade/quote/TBILL-26?amount=5")).await;
+        assert_eq!(status, StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(body["error"], "Blockchain error: rpc timeout");
+    }
+
+    #[tokio::test]
+    async fn settlement_must_match_its_quote() {
+        let state = state_with(config(), |_| {}).await;
+        state.registry.insert(serde_json::from_value(gold()).unwrap()).unwrap();
+        let app = app(state.clone()).unwrap();
+        let (quote_id, _) = quote(&app, 5).await;
+
+        for request in [
+            // More units than quoted
+            buy(settlement("TBILL-26", 5_000, &quote_id)),
+            // Another asset in the body
+            buy(settlement("GOLD-1", 5, &quote_id)),
+            // Another asset in the path
+            post_json("/api/v1/trade/buy/GOLD-1", &settlement("TBILL-26", 5, &quote_id)),
+        ] {
+            let (status, _, body) = send(&app, request).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+            assert_eq!(body["error"], "Invalid request: quote mismatch");
+        }
+        assert_eq!(settled_count(&state).await, 0);
+
+        // The same asset by its address still matches
+        let address = "0x0cB59FaA219b80D8FbD28E9D37008f2db10F847A";
+        let (status, _, receipt) = send(&app, buy(settlement(address, 5, &quote_id))).await;
+        assert_eq!(status, StatusCode::OK, "{}", receipt);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn concurrent_quotes_are_all_stored() {
+        let state = state_with(config(), |_| {}).await;
+        let app = app(state.clone()).unwrap();
+
+        let tasks: Vec<_> = (1..=64u64)
+            .map(|amount| {
+                let app = app.clone();
+                tokio::spawn(async move { (amount, quote(&app, amount).await.0) })
+            })
+            .collect();
+        let timeout = std::time::Duration::from_secs(10);
+        for task in tasks {
+            let (amount, quote_id) = tokio::time::timeout(timeout, task).await.expect("deadlock").unwrap();
+            let stored = state.stores.quotes.get(&quote_id).expect("quote lost");
+            assert_eq!((stored.asset_id.as_str(), stored.amount), ("TBILL-26", amount));
+        }
+    }
+
+    #[tokio::test]
+    async fn zero_and_oversized_amounts_are_refused_before_quoting() {
+        let app = app_with(config()).await;
+        for (amount, error) in [
+            (0u64, "amount must be positive"),
+            (1_000_000_000_001, "amount must be at most 1000000000000"),
+        ] {
+            for uri in [
+                format!("/api/v1/trade/quote/TBILL-26?amount={}", amount),
+                format!("/api/v1/trade/buy/TBILL-26?amount={}", amount),
+            ] {
+                let (status, _, body) = send(&app, get(&uri)).await;
+                assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+                assert_eq!(body["error"], format!("Invalid request: {}", error));
+            }
+        }
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn health_degrades_once_the_cached_head_is_stale() {
+        let state = state_with(Config { health_stale_secs: 30, ..config() }, |_| {}).await;
+        let app = app(state.clone()).unwrap();
+
+        // Nothing polled yet
+        let (status, _, health) = send(&app, get("/health")).await;
+        assert_eq!((status, health["status"].as_str()), (StatusCode::SERVICE_UNAVAILABLE, Some("degraded")));
+
+        state.chain_head.record(12345678);
+        tokio::time::advance(std::time::Duration::from_secs(30)).await;
+        let (status, _, health) = send(&app, get("/health")).await;
+        assert_eq!((status, health["status"].as_str()), (StatusCode::OK, Some("healthy")));
+        assert_eq!(health["block_age_secs"], 30);
+
+        // The poller stopped updating
+        tokio::time::advance(std::time::Duration::from_secs(1)).await;
+        let (status, _, health) = send(&app, get("/health")).await;
+        assert_eq!((status, health["status"].as_str()), (StatusCode::SERVICE_UNAVAILABLE, Some("degraded")));
+        assert_eq!(health["block_number"], 12345678);
+    }
+
+    #[tokio::test]
+    async fn overflowing_total_price_is_a_bad_request() {
+        let app = app_with(Config { min_fee_usdc: u64::MAX, ..config() }).await;
+        for uri in ["/api/v1/trade/quote/TBILL-26?amount=1", "/api/v1/trade/buy/TBILL-26?amount=1"] {
+            let (status, _, body) = send(&app, get(uri)).await;
+            assert_eq!(status, StatusCode::BAD_REQUEST, "{}", uri);
+            assert_eq!(body["error"], "Invalid request: Total price overflows");
+        }
+    }
+
+    fn verify_request(proof_bytes: usize) -> serde_json::Value {
+        serde_json::json!({
+            "circuit_id": "0xDd2ffa97F680032332EA4905586e2366584Ae0be",
+            "proof": format!("0x{}", "ab".repeat(proof_bytes)),
+            "public_values": format!("0x{}", "00".repeat(96)),
+        })
+    }
+
+    #[tokio::test]
+    async fn verify_checks_only_the_public_values() {
+        let app = app_with(config()).await;
+        let (quote_id, _) = quote(&app, 1).await;
+        let signed = settlement("TBILL-26", 1, &quote_id);
+        let request = serde_json::json!({
+            "circuit_id": "0xDd2ffa97F680032332EA4905586e2366584Ae0be",
+            // Not a real proof: the bytes are never verified here
+            "proof": signed["compliance_proof"],
+            "public_values": signed["public_values"],
+        });
+        let (status, _, result) = send(&app, post_json("/api/v1/verify", &request)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(result["public_values_ok"], true, "{}", result);
+        assert_eq!(result["committed"]["agent_address"].as_str().map(str::len), Some(42));
+
+        // Zeroed public values expired at the epoch
+        let (_, _, result) = send(&app, post_json("/api/v1/verify", &verify_request(64))).await;
+        assert_eq!(result["public_values_ok"], false);
+        assert_eq!(result["reason"], "verification expired");
+        assert!(result.get("committed").is_none());
+    }
+
+    #[tokio::test]
+    async fn request_bodies_are_limited_in_size_and_type() {
+        let app = app_with(Config { max_body_bytes: 4096, ..config() }).await;
+
+        let (status, _, body) = send(&app, post_json("/api/v1/verify", &verify_request(64))).await;
+        assert_eq!(status, StatusCode::OK, "{}", body);
+
+        let (status, _, _) = send(&app, post_json("/api/v1/verify", &verify_request(4096))).await;
+        assert_eq!(status, StatusCode::PAYLOAD_TOO_LARGE);
+
+        let body = verify_request(64).to_string();
+        let text = Request::post("/api/v1/verify")
+            .header(header::CONTENT_TYPE, "text/plain")
+            .header(header::CONTENT_LENGTH, body.len())
+            .body(Body::from(body))
+            .unwrap();
+        let (status, _, body) = send(&app, text).await;
+        assert_eq!(status, StatusCode::UNSUPPORTED_MEDIA_TYPE);
+        assert_eq!(body["error"], "Unsupported content type: text/plain (expected application/json)");
+    }
+
+    #[tokio::test]
+    async fn proof_field_has_its_own_cap() {
+        // Within the body limit, over the 2048-byte proof cap
+        let app = app_with(config()).await;
+        let (status, _, body) = send(&app, post_json("/api/v1/verify", &verify_request(2049))).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"].as_str().unwrap().contains("too long"), "{}", body);
+    }
 }
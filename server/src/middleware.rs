@@ -1,3 +1,106 @@
 //! Middleware for rate limiting, logging, etc.
 
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use anyhow::{Context, Result};
+use axum::extract::Request;
+use axum::http::{header, HeaderName, HeaderValue, Method};
+use axum::middleware::Next;
+use axum::response::{IntoResponse, Response};
+use tower_http::cors::{AllowOrigin, Any, CorsLayer};
+use tracing::Instrument;
+
+use crate::config::Config;
+use crate::error::AppError;
+
+/// CORS policy from `ALLOWED_ORIGINS`
+///
+/// Only listed origins are reflected in `Access-Control-Allow-Origin`; any
+/// origin is allowed only when the list is exactly `*`.
+pub fn cors_layer(config: &Config) -> Result<CorsLayer> {
+    if config.allowed_origins.iter().any(|origin| origin == "*") {
+        return Ok(CorsLayer::new()
+            .allow_origin(Any)
+            .allow_methods(Any)
+            .allow_headers(Any));
+    }
+
+    let origins = config
+        .allowed_origins
+        .iter()
+        .map(|origin| {
+            origin
+                .parse::<HeaderValue>()
+                .with_context(|| format!("Invalid origin in ALLOWED_ORIGINS: {}", origin))
+        })
+        .collect::<Result<Vec<_>>>()?;
+
+    Ok(CorsLayer::new()
+        .allow_origin(AllowOrigin::list(origins))
+        .allow_methods([Method::GET, Method::POST, Method::DELETE])
+        .allow_headers([header::CONTENT_TYPE, header::AUTHORIZATION]))
+}
+
+static X_REQUEST_ID: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Tag every response with `X-Request-Id`, echoing the caller's if it sent one
+///
+/// Agents record the id in their receipts so a settlement can be matched to
+/// the server's logs.
+pub async fn request_id(request: Request, next: Next) -> Response {
+    let id = request
+        .headers()
+        .get(&X_REQUEST_ID)
+        .cloned()
+        .unwrap_or_else(new_request_id);
+
+    let span = tracing::info_span!("request", request_id = ?id);
+    let mut response = next.run(request).instrument(span).await;
+    response.headers_mut().insert(X_REQUEST_ID.clone(), id);
+    response
+}
+
+/// Process start time plus a counter: unique per process, ordered within it
+fn new_request_id() -> HeaderValue {
+    static NEXT: AtomicU64 = AtomicU64::new(0);
+    static STARTED: std::sync::OnceLock<u64> = std::sync::OnceLock::new();
+
+    let started = *STARTED.get_or_init(|| {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs()
+    });
+    let n = NEXT.fetch_add(1, Ordering::Relaxed);
+    HeaderValue::from_str(&format!("{:x}-{:08x}", started, n)).unwrap()
+}
+
+/// Reject request bodies that aren't `application/json` with 415
+///
+/// Body-less requests (GETs, DELETEs) pass through whatever their headers say.
+pub async fn require_json(request: Request, next: Next) -> Response {
+    let has_body = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .is_some_and(|len| len != "0")
+        || request.headers().contains_key(header::TRANSFER_ENCODING);
+    if !has_body {
+        return next.run(request).await;
+    }
+
+    let content_type = request
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or("");
+    let essence = content_type.split(';').next().unwrap_or("").trim();
+    if !essence.eq_ignore_ascii_case("application/json") {
+        let got = if content_type.is_empty() { "none" } else { content_type };
+        return AppError::UnsupportedMediaType(format!("{} (expected application/json)", got))
+            .into_response();
+    }
+    next.run(request).await
+}
+
 // Future: rate limiting, request validation, agent authentication
@@ -14,6 +14,18 @@ pub struct Asset {
     pub currency: String,
     pub compliance_circuit: String,
     pub active: bool,
+    /// Overrides the global quote validity for this asset, clamped to 10-3600s
+    ///
+    /// This is the length of the window quotes expire at the end of, not the
+    /// exact lifetime of each quote (see `Quote::expires_in_seconds`).
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub quote_validity_seconds: Option<u64>,
+    /// Smallest number of units a quote may be for; unset is unbounded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_amount: Option<u64>,
+    /// Largest number of units a quote may be for; unset is unbounded
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_amount: Option<u64>,
 }
 
 /// Quote for purchasing an asset
@@ -23,21 +35,40 @@ pub struct Quote {
     pub amount: u64,
     pub price_per_unit: u64,
     pub total_price: u64,
+    pub fee_bps: u64, // Contract fee rate the fee was priced at
     pub fee: u64,
+    /// End of the validity window the quote was issued in
     pub expiry: u64,
+    pub expiry_iso: String, // RFC 3339 form of `expiry`
+    /// `expiry - now` at issue: between 10s and the validity plus 10s, since
+    /// expiries are window-aligned
+    pub expires_in_seconds: u64,
     pub quote_id: String,
+    /// USDC of a `value_usdc` request too small to buy another unit
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remainder_usdc: Option<u64>,
 }
 
-/// x402 Challenge headers
+/// x402 challenge body, mirrored in the `X-402-*` headers
 #[derive(Debug, Clone, Serialize)]
 pub struct X402Challenge {
-    pub asset_id: String,
-    pub price: u64,
+    pub error: String,
+    pub message: String,
+    pub protocol: String,
+    pub asset: String,
+    pub asset_address: String,
+    pub amount: u64,
+    pub base_price: u64,
+    pub fee: u64,
+    pub total_price: u64, // base_price + fee
     pub currency: String,
-    pub compliance_circuit: String,
-    pub payment_address: String,
     pub expiry: u64,
     pub quote_id: String,
+    pub compliance_circuit: String,
+    pub payment_address: String,
+    pub chain_id: u64,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub remainder_usdc: Option<u64>,
 }
 
 /// Settlement request from agent
@@ -49,6 +80,7 @@ pub struct SettlementRequest {
     pub compliance_proof: String, // Hex-encoded SP1 proof
     pub public_values: String,    // Hex-encoded public values
     pub payment_signature: Option<String>, // For permit-based payments
+    pub request_signature: String,         // EIP-191 signature by the proven agent
 }
 
 /// Settlement response
@@ -60,6 +92,30 @@ pub struct SettlementResponse {
     pub amount: u64,
     pub settlement_id: String,
     pub timestamp: u64,
+    pub block_number: u64,
+    pub confirmations: u64,
+}
+
+/// Finality of a submitted settlement
+#[derive(Debug, Clone, Serialize)]
+pub struct SettlementStatusResponse {
+    pub tx_hash: String,
+    pub status: SettlementStatus,
+    pub block_number: u64,
+    pub confirmations: u64,
+    pub required_confirmations: u64,
+}
+
+/// Predicted outcome of a settlement, from `eth_call` without sending it
+#[derive(Debug, Clone, Serialize)]
+pub struct SimulationResponse {
+    pub would_succeed: bool,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub gas_estimate: Option<u64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub revert_reason: Option<String>,
+    /// Price the settlement would pull, including fee
+    pub total_price: u64,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -70,6 +126,42 @@ pub enum SettlementStatus {
     Failed,
 }
 
+/// Standalone proof verification request
+#[derive(Debug, Clone, Deserialize)]
+pub struct VerifyRequest {
+    pub circuit_id: String,
+    pub proof: String,         // Hex-encoded SP1 proof
+    pub public_values: String, // Hex-encoded public values
+}
+
+/// Values a compliance proof commits to
+#[derive(Debug, Clone, Serialize)]
+pub struct CommittedValues {
+    pub agent_address: String,
+    pub valid_until: u64,
+    pub jurisdiction_hash: String,
+    /// Proving time, for circuits that commit it
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub current_time: Option<u64>,
+}
+
+/// Standalone proof verification result
+#[derive(Debug, Clone, Serialize)]
+///
+/// Only the public values are checked here; the proof bytes are verified
+/// on-chain at settlement.
+pub struct VerifyResponse {
+    /// Whether the public values are well-formed, unexpired and within the
+    /// time tolerance
+    pub public_values_ok: bool,
+    /// Why the public values were rejected
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub reason: Option<String>,
+    /// Decoded public values, present when they passed every check
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub committed: Option<CommittedValues>,
+}
+
 /// Agent verification status
 #[derive(Debug, Clone, Serialize)]
 pub struct AgentStatus {
@@ -88,6 +180,7 @@ pub struct ComplianceCircuit {
     pub description: String,
     pub ipfs_hash: String,
     pub verifier_address: String,
+    pub vk_hash: Option<String>, // None unless a real key is loaded for the circuit
     pub required_claims: Vec<String>,
 }
 
@@ -97,6 +190,9 @@ pub struct HealthResponse {
     pub status: String,
     pub chain_id: u64,
     pub block_number: u64,
+    /// Seconds since `block_number` was read, absent before the first read
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub block_age_secs: Option<u64>,
     pub clearinghouse: String,
     pub version: String,
 }
@@ -0,0 +1,181 @@
+//! Request and quote authentication
+//!
+//! Agents sign each settlement with the key bound into their compliance proof,
+//! so the server can reject requests from anyone who merely replays a proof.
+//! The server in turn signs its x402 quotes with the relay key, so agents can
+//! detect a tampered price or payment address.
+
+use ethers::signers::LocalWallet;
+use ethers::types::{Address, Signature, U256};
+use ethers::utils::{hash_message, keccak256};
+
+use crate::error::AppError;
+
+/// Digest an agent signs (EIP-191 personal_sign) to authorize a settlement
+///
+/// Equivalent to `keccak256(abi.encode(keccak256(asset), amount,
+/// keccak256(quote_id), keccak256(proof), keccak256(public_values)))`.
+pub fn settlement_digest(
+    asset: &str,
+    amount: u64,
+    quote_id: &str,
+    compliance_proof: &[u8],
+    public_values: &[u8],
+) -> [u8; 32] {
+    let mut amount_word = [0u8; 32];
+    U256::from(amount).to_big_endian(&mut amount_word);
+
+    let mut encoded = Vec::with_capacity(5 * 32);
+    encoded.extend_from_slice(&keccak256(asset.as_bytes()));
+    encoded.extend_from_slice(&amount_word);
+    encoded.extend_from_slice(&keccak256(quote_id.as_bytes()));
+    encoded.extend_from_slice(&keccak256(compliance_proof));
+    encoded.extend_from_slice(&keccak256(public_values));
+    keccak256(encoded)
+}
+
+/// Digest the server signs over an x402 quote
+///
+/// Equivalent to `keccak256(abi.encode(keccak256(asset), amount, totalPrice,
+/// paymentAddress, expiry, keccak256(quote_id), chainId))`.
+pub fn quote_digest(
+    asset: &str,
+    amount: u64,
+    total_price: u64,
+    payment_address: Address,
+    expiry: u64,
+    quote_id: &str,
+    chain_id: u64,
+) -> [u8; 32] {
+    let word = |value: u64| {
+        let mut word = [0u8; 32];
+        U256::from(value).to_big_endian(&mut word);
+        word
+    };
+    
+    let mut encoded = Vec::with_capacity(7 * 32);
+    encoded.extend_from_slice(&keccak256(asset.as_bytes()));
+    encoded.extend_from_slice(&word(amount));
+    encoded.extend_from_slice(&word(total_price));
+    encoded.extend_from_slice(&[0u8; 12]);
+    encoded.extend_from_slice(payment_address.as_bytes());
+    encoded.extend_from_slice(&word(expiry));
+    encoded.extend_from_slice(&keccak256(quote_id.as_bytes()));
+    encoded.extend_from_slice(&word(chain_id));
+    keccak256(encoded)
+}
+
+/// EIP-191 sign a digest with the server's relay key
+pub fn sign_digest(signer: &LocalWallet, digest: &[u8; 32]) -> Result<String, AppError> {
+    let signature = signer
+        .sign_hash(hash_message(digest))
+        .map_err(|e| AppError::Internal(format!("Quote signing failed: {}", e)))?;
+    Ok(format!("0x{}", hex::encode(signature.to_vec())))
+}
+
+/// Agent address committed in the compliance public values
+///
+/// Public values are ABI encoded as `(address agent, uint256 validUntil, bytes32 jurisdictionHash)`.
+pub fn committed_agent(public_values: &[u8]) -> Result<Address, AppError> {
+    if public_values.len() < 32 {
+        return Err(AppError::BadRequest("Public values too short".to_string()));
+    }
+    Ok(Address::from_slice(&public_values[12..32]))
+}
+
+/// Recover the signer of `digest` and check it is the agent bound to the proof
+pub fn verify_agent_signature(
+    digest: &[u8; 32],
+    signature: &str,
+    public_values: &[u8],
+) -> Result<Address, AppError> {
+    let signature: Signature = signature
+        .trim_start_matches("0x")
+        .parse()
+        .map_err(|e| AppError::BadRequest(format!("Invalid request signature: {}", e)))?;
+
+    let signer = signature
+        .recover(&digest[..])
+        .map_err(|_| AppError::Unauthorized("Signature recovery failed".to_string()))?;
+
+    let agent = committed_agent(public_values)?;
+    if signer != agent {
+        return Err(AppError::Unauthorized(format!(
+            "Request signed by {:?}, proof is bound to {:?}",
+            signer, agent
+        )));
+    }
+
+    Ok(signer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ethers::signers::Signer;
+
+    /// Anvil/Hardhat account #1, the key used by the agent CLI examples
+    const AGENT_KEY: &str = "59c6995e998f97a5a0044966f0945389dc9e86dae88c7a8412f4603b6b78690d";
+
+    /// Anvil/Hardhat account #0, standing in for a forger
+    const OTHER_KEY: &str = "ac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+
+    fn public_values_for(agent: Address) -> Vec<u8> {
+        let mut values = vec![0u8; 96];
+        values[12..32].copy_from_slice(agent.as_bytes());
+        values
+    }
+
+    fn digest() -> [u8; 32] {
+        settlement_digest("TBILL-26", 5, "0xfeed", &[1, 2, 3], &[4, 5, 6])
+    }
+
+    // The agent CLI pins the same vectors, so the two copies cannot drift apart
+    #[test]
+    fn settlement_digest_matches_pinned_vector() {
+        assert_eq!(
+            hex::encode(digest()),
+            "d03c1eea6a7298df75ca768ae93526e79957e573c536c78e919b93f149698579"
+        );
+    }
+
+    #[test]
+    fn quote_digest_matches_pinned_vector() {
+        let payment_address: Address = "0xb315C8F827e3834bB931986F177cb1fb6D20415D".parse().unwrap();
+        let digest = quote_digest("TBILL-26", 5, 4_902_450, payment_address, 1_700_000_100, "0xfeed", 8453);
+        assert_eq!(
+            hex::encode(digest),
+            "56c34f66e4cfa4d62692a3df92ea2368b706eb8398b495b9e8c7e6e3b0fff6f7"
+        );
+    }
+
+    #[test]
+    fn signature_by_the_proven_agent_is_accepted() {
+        let agent: LocalWallet = AGENT_KEY.parse().unwrap();
+        let signature = sign_digest(&agent, &digest()).unwrap();
+        let signer = verify_agent_signature(&digest(), &signature, &public_values_for(agent.address())).unwrap();
+        assert_eq!(signer, agent.address());
+    }
+
+    #[test]
+    fn forged_signature_is_rejected() {
+        let agent: LocalWallet = AGENT_KEY.parse().unwrap();
+        let forger: LocalWallet = OTHER_KEY.parse().unwrap();
+        let public_values = public_values_for(agent.address());
+
+        // Signed by someone other than the agent bound to the proof
+        let forged = sign_digest(&forger, &digest()).unwrap();
+        assert!(matches!(
+            verify_agent_signature(&digest(), &forged, &public_values),
+            Err(AppError::Unauthorized(_))
+        ));
+
+        // The agent's signature over different terms
+        let other_terms = settlement_digest("TBILL-26", 500, "0xfeed", &[1, 2, 3], &[4, 5, 6]);
+        let replayed = sign_digest(&agent, &other_terms).unwrap();
+        assert!(matches!(
+            verify_agent_signature(&digest(), &replayed, &public_values),
+            Err(AppError::Unauthorized(_))
+        ));
+    }
+}
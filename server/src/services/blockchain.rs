@@ -1,117 +1,25 @@
 //! Blockchain interaction service
+//!
+//! The server runs against the mock chain in `blockchain_mock` unless it is
+//! built with `--features alloy`, which swaps in the Alloy-backed service.
 
-use crate::config::Config;
-use crate::error::AppError;
-use crate::models::{AgentStatus, Asset};
+#[cfg(not(feature = "alloy"))]
+pub use crate::services::blockchain_mock::BlockchainService;
+#[cfg(feature = "alloy")]
+pub use crate::services::blockchain_alloy::BlockchainServiceAlloy as BlockchainService;
 
-/// Service for interacting with Base blockchain
-pub struct BlockchainService {
-    rpc_url: String,
-    clearinghouse_address: String,
-    // In production: ethers::Provider, wallet, contract instances
+/// A mined settlement transaction
+#[derive(Debug, Clone)]
+pub struct SettlementReceipt {
+    pub tx_hash: String,
+    pub block_number: u64,
 }
 
-impl BlockchainService {
-    pub async fn new(config: &Config) -> anyhow::Result<Self> {
-        Ok(Self {
-            rpc_url: config.rpc_url.clone(),
-            clearinghouse_address: config.clearinghouse_address.clone(),
-        })
-    }
-    
-    /// Get current block number
-    pub async fn get_block_number(&self) -> Result<u64, AppError> {
-        // In production: self.provider.get_block_number().await
-        // Mock for demo
-        Ok(12345678)
-    }
-    
-    /// Get all listed assets from clearinghouse
-    pub async fn get_listed_assets(&self) -> Result<Vec<Asset>, AppError> {
-        // In production: query contract events or registry
-        // Mock data for demo
-        Ok(vec![Asset {
-            id: "TBILL-26".to_string(),
-            name: "Treasury Bill Oct 2026".to_string(),
-            symbol: "TBILL-26".to_string(),
-            address: "0x0cB59FaA219b80D8FbD28E9D37008f2db10F847A".to_string(),
-            issuer: "0xc7554F1B16ad0b3Ce363d53364C9817743E32f90".to_string(),
-            price_per_unit: 980_000, // $0.98 in atomic USDC
-            currency: "USDC".to_string(),
-            compliance_circuit: "0xDd2ffa97F680032332EA4905586e2366584Ae0be".to_string(),
-            active: true,
-        }])
-    }
-    
-    /// Get specific asset details
-    pub async fn get_asset(&self, asset_id: &str) -> Result<Option<Asset>, AppError> {
-        let assets = self.get_listed_assets().await?;
-        Ok(assets.into_iter().find(|a| a.id == asset_id || a.address == asset_id))
-    }
-    
-    /// Execute settlement on-chain
-    pub async fn execute_settlement(
-        &self,
-        asset_address: &str,
-        amount: u64,
-        quote_expiry: u64,
-        compliance_proof: &[u8],
-        public_values: &[u8],
-    ) -> Result<String, AppError> {
-        tracing::info!(
-            "Executing settlement: asset={}, amount={}, proof_len={}, values_len={}",
-            asset_address,
-            amount,
-            compliance_proof.len(),
-            public_values.len()
-        );
-        
-        // In production:
-        // 1. Build transaction data
-        // 2. Estimate gas
-        // 3. Send transaction
-        // 4. Wait for confirmation
-        
-        /*
-        let calldata = clearinghouse_contract
-            .settle(
-                asset_address.parse()?,
-                amount.into(),
-                quote_expiry.into(),
-                compliance_proof.into(),
-                public_values.into(),
-            )
-            .calldata();
-        
-        let tx = wallet
-            .send_transaction(tx_request, None)
-            .await?
-            .await?;
-        */
-        
-        // Mock response for demo
-        let mock_tx_hash = format!(
-            "0x{:064x}",
-            std::time::SystemTime::now()
-                .duration_since(std::time::UNIX_EPOCH)
-                .unwrap()
-                .as_nanos()
-        );
-        
-        tracing::info!("Settlement complete: tx={}", mock_tx_hash);
-        
-        Ok(mock_tx_hash)
-    }
-    
-    /// Get agent verification status
-    pub async fn get_agent_status(&self, address: &str) -> Result<AgentStatus, AppError> {
-        // In production: query clearinghouse.agentVerifiedUntil(address)
-        Ok(AgentStatus {
-            address: address.to_string(),
-            verified: false,
-            verified_until: None,
-            total_settlements: 0,
-            total_volume_usdc: 0,
-        })
-    }
+/// Outcome of simulating `settle(...)` with `eth_call`
+#[derive(Debug, Clone)]
+pub struct SettlementSimulation {
+    /// Present when the call would succeed
+    pub gas_estimate: Option<u64>,
+    /// Present when the call would revert
+    pub revert_reason: Option<String>,
 }
@@ -6,52 +6,71 @@ use alloy::{
     network::EthereumWallet,
     primitives::{Address, Bytes, FixedBytes, U256},
     providers::{Provider, ProviderBuilder, RootProvider},
+    rpc::types::{BlockId, Filter},
     signers::local::PrivateKeySigner,
     sol,
-    transports::http::{Client, Http},
+    sol_types::SolEvent,
+    transports::http::{reqwest::Url, Client, Http},
 };
 use anyhow::{Context, Result};
-use std::sync::Arc;
+use std::future::IntoFuture;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, Instant};
 
 use crate::config::Config;
 use crate::error::AppError;
 use crate::models::{AgentStatus, Asset};
+use crate::services::blockchain::{SettlementReceipt, SettlementSimulation};
+use crate::services::indexer::SettlementLog;
+use crate::services::registry::{find_asset, AssetRegistry};
 
 // Generate contract bindings
-sol!(
+sol! {
     #[sol(rpc)]
-    Clearinghouse402,
-    r#"[
-        function settle(address asset, uint256 amount, uint256 quoteExpiry, bytes calldata complianceProof, bytes calldata publicValues) external returns (bytes32 txId)
-        function getQuote(address asset, uint256 amount) external view returns ((address asset, uint256 amount, uint256 totalPrice, uint256 expiry, bytes32 quoteId) quote)
-        function isAgentVerified(address agent) external view returns (bool)
-        function agentVerifiedUntil(address agent) external view returns (uint256)
-        function assets(address asset) external view returns (address issuer, bytes32 complianceCircuit, uint256 pricePerUnit, bool active)
-        function feeBps() external view returns (uint256)
-        event Settlement(address indexed agent, address indexed asset, uint256 amount, uint256 price, bytes32 indexed txId)
-    ]"#
-);
+    contract Clearinghouse402 {
+        struct Quote {
+            address asset;
+            uint256 amount;
+            uint256 totalPrice;
+            uint256 expiry;
+            bytes32 quoteId;
+        }
+
+        function settle(address asset, uint256 amount, uint256 quoteExpiry, bytes calldata complianceProof, bytes calldata publicValues) external returns (bytes32 txId);
+        function getQuote(address asset, uint256 amount) external view returns (Quote memory quote);
+        function isAgentVerified(address agent) external view returns (bool);
+        function agentVerifiedUntil(address agent) external view returns (uint256);
+        function assets(address asset) external view returns (address issuer, bytes32 complianceCircuit, uint256 pricePerUnit, bool active);
+        function feeBps() external view returns (uint256);
+        event Settlement(address indexed agent, address indexed asset, uint256 amount, uint256 price, bytes32 indexed txId);
+    }
+}
 
-sol!(
+sol! {
     #[sol(rpc)]
-    IERC20,
-    r#"[
-        function balanceOf(address account) external view returns (uint256)
-        function allowance(address owner, address spender) external view returns (uint256)
-        function approve(address spender, uint256 amount) external returns (bool)
-    ]"#
-);
+    interface IERC20 {
+        function balanceOf(address account) external view returns (uint256);
+        function allowance(address owner, address spender) external view returns (uint256);
+        function approve(address spender, uint256 amount) external returns (bool);
+    }
+}
+
+/// How long a feeBps() read is reused
+const FEE_CACHE_TTL: Duration = Duration::from_secs(60);
 
 pub struct BlockchainServiceAlloy {
     provider: Arc<RootProvider<Http<Client>>>,
+    rpc_url: Url,
     wallet: Option<EthereumWallet>,
     clearinghouse_address: Address,
     usdc_address: Address,
-    chain_id: u64,
+    registry: Arc<dyn AssetRegistry>,
+    fee_bps_cache: RwLock<Option<(u64, Instant)>>,
+    rpc_timeout: Duration,
 }
 
 impl BlockchainServiceAlloy {
-    pub async fn new(config: &Config) -> Result<Self> {
+    pub async fn new(config: &Config, registry: Arc<dyn AssetRegistry>) -> Result<Self> {
         // Parse addresses
         let clearinghouse_address: Address = config
             .clearinghouse_address
@@ -64,8 +83,8 @@ impl BlockchainServiceAlloy {
             .context("Invalid USDC address")?;
         
         // Build provider
-        let provider = ProviderBuilder::new()
-            .on_http(config.rpc_url.parse().context("Invalid RPC URL")?);
+        let rpc_url: Url = config.rpc_url.parse().context("Invalid RPC URL")?;
+        let provider = ProviderBuilder::new().on_http(rpc_url.clone());
         
         // Optionally load wallet for relay transactions
         let wallet = if let Some(ref pk) = config.private_key {
@@ -77,73 +96,137 @@ impl BlockchainServiceAlloy {
         
         Ok(Self {
             provider: Arc::new(provider),
+            rpc_url,
             wallet,
             clearinghouse_address,
             usdc_address,
-            chain_id: config.chain_id,
+            registry,
+            fee_bps_cache: RwLock::new(None),
+            rpc_timeout: Duration::from_secs(config.rpc_timeout_secs),
         })
     }
     
+    /// Bound an RPC call by `RPC_TIMEOUT_SECS` so a hung connection can't stall a worker
+    async fn timed<F: IntoFuture>(&self, call: F) -> Result<F::Output, AppError> {
+        tokio::time::timeout(self.rpc_timeout, call)
+            .await
+            .map_err(|_| AppError::BlockchainError("rpc timeout".to_string()))
+    }
+    
     /// Get current block number
     pub async fn get_block_number(&self) -> Result<u64, AppError> {
-        self.provider
-            .get_block_number()
-            .await
+        self.timed(self.provider.get_block_number())
+            .await?
             .map_err(|e| AppError::BlockchainError(e.to_string()))
     }
     
+    /// Get the clearinghouse fee in basis points (cached)
+    pub async fn get_fee_bps(&self) -> Result<u64, AppError> {
+        if let Some((fee_bps, read_at)) = *self.fee_bps_cache.read().unwrap() {
+            if read_at.elapsed() < FEE_CACHE_TTL {
+                return Ok(fee_bps);
+            }
+        }
+        
+        let contract = Clearinghouse402::new(self.clearinghouse_address, &*self.provider);
+        let fee_bps: u64 = self
+            .timed(contract.feeBps().call())
+            .await?
+            .map_err(|e| AppError::BlockchainError(format!("feeBps() failed: {}", e)))?
+            ._0
+            .try_into()
+            .map_err(|_| AppError::BlockchainError("feeBps() out of range".to_string()))?;
+        
+        *self.fee_bps_cache.write().unwrap() = Some((fee_bps, Instant::now()));
+        Ok(fee_bps)
+    }
+    
     /// Get all listed assets from clearinghouse
     pub async fn get_listed_assets(&self) -> Result<Vec<Asset>, AppError> {
-        // In production, we'd query AssetListed events or maintain a registry
-        // For now, return known test assets
-        
+        // Registry entries are refreshed from the contract's listing
         let contract = Clearinghouse402::new(self.clearinghouse_address, &*self.provider);
         
-        // This is a simplified version - in production, iterate over events
-        // For MVP, we hardcode the test TBILL address
-        let test_tbill: Address = "0x1234567890123456789012345678901234567890"
-            .parse()
-            .unwrap();
-        
-        match contract.assets(test_tbill).call().await {
-            Ok(result) => {
-                if result.active {
-                    Ok(vec![Asset {
-                        id: "TBILL-26".to_string(),
-                        name: "Treasury Bill Oct 2026".to_string(),
-                        symbol: "TBILL-26".to_string(),
-                        address: format!("{:?}", test_tbill),
-                        issuer: format!("{:?}", result.issuer),
-                        price_per_unit: result.pricePerUnit.try_into().unwrap_or(0),
-                        currency: "USDC".to_string(),
-                        compliance_circuit: format!("{:?}", result.complianceCircuit),
-                        active: result.active,
-                    }])
-                } else {
-                    Ok(vec![])
-                }
-            }
-            Err(_) => {
-                // Return mock data if contract query fails (e.g., on testnet)
-                Ok(vec![Asset {
-                    id: "TBILL-26".to_string(),
-                    name: "Treasury Bill Oct 2026".to_string(),
-                    symbol: "TBILL-26".to_string(),
-                    address: format!("{:?}", test_tbill),
-                    issuer: "0xISSUER".to_string(),
-                    price_per_unit: 980_000,
-                    currency: "USDC".to_string(),
-                    compliance_circuit: "0xABCDEF1234567890ABCDEF1234567890ABCDEF1234567890ABCDEF1234567890".to_string(),
-                    active: true,
-                }])
+        let mut assets = Vec::new();
+        for mut asset in self.registry.list() {
+            let address: Address = asset
+                .address
+                .parse()
+                .map_err(|_| AppError::Internal(format!("Invalid registry address: {}", asset.address)))?;
+            
+            let result = self
+                .timed(contract.assets(address).call())
+                .await?
+                .map_err(|e| AppError::BlockchainError(format!("assets({}) failed: {}", asset.id, e)))?;
+            // Inactive means delisted on-chain
+            if result.active {
+                asset.issuer = format!("{:?}", result.issuer);
+                asset.price_per_unit = result.pricePerUnit.try_into().map_err(|_| {
+                    AppError::BlockchainError(format!("pricePerUnit out of range for {}", asset.id))
+                })?;
+                asset.compliance_circuit = format!("{:?}", result.complianceCircuit);
+                assets.push(asset);
             }
         }
+        
+        Ok(assets)
     }
     
     /// Get specific asset details
     pub async fn get_asset(&self, asset_id: &str) -> Result<Option<Asset>, AppError> {
         let assets = self.get_listed_assets().await?;
-        Ok(assets.into_iter().find(|a| a.id == asset_id || a.address.contains(asset_id)))
+        find_asset(assets, asset_id)
+    }
+    
+    /// Simulate settlement with `eth_call` from the relay wallet
+    ///
+    /// A revert is reported in the result rather than as an error; nothing
+    /// is signed or sent.
+    pub async fn simulate_settlement(
+        &self,
+        asset_address: &str,
+        amount: u64,
+        quote_expiry: u64,
+        compliance_proof: &[u8],
+        public_values: &[u8],
+    ) -> Result<SettlementSimulation, AppError> {
+        let wallet = self.wallet.as_ref()
+            .ok_or_else(|| AppError::Internal("No relay wallet configured".to_string()))?;
+        
+        let asset: Address = asset_address
+            .parse()
+            .map_err(|_| AppError::BadRequest("Invalid asset address".to_string()))?;
+        
+        // settle() checks compliance against msg.sender, so call as the relay
+        let provider_with_wallet = ProviderBuilder::new()
+            .with_recommended_fillers()
+            .wallet(wallet.clone())
+            .on_http(self.rpc_url.clone());
+        
+        let contract = Clearinghouse402::new(self.clearinghouse_address, &provider_with_wallet);
+        let tx = contract.settle(
+            asset,
+            U256::from(amount),
+            U256::from(quote_expiry),
+            Bytes::from(compliance_proof.to_vec()),
+            Bytes::from(public_values.to_vec()),
+        );
+        
+        if let Err(e) = self.timed(tx.call()).await? {
+            return Ok(SettlementSimulation {
+                gas_estimate: None,
+                revert_reason: Some(e.to_string()),
+            });
+        }
+        
+        let gas_estimate = self
+            .timed(tx.estimate_gas())
+            .await?
+            .map_err(|e| AppError::BlockchainError(format!("Gas estimation failed: {}", e)))?;
+        
+        Ok(SettlementSimulation {
+            gas_estimate: Some(gas_estimate as u64),
+            revert_reason: None,
+        })
     }
     
     /// Execute settlement on-chain
@@ -156,7 +239,7 @@ impl BlockchainServiceAlloy {
         quote_expiry: u64,
         compliance_proof: &[u8],
         public_values: &[u8],
-    ) -> Result<String, AppError> {
+    ) -> Result<SettlementReceipt, AppError> {
         let wallet = self.wallet.as_ref()
             .ok_or_else(|| AppError::Internal("No relay wallet configured".to_string()))?;
         
@@ -175,7 +258,7 @@ impl BlockchainServiceAlloy {
         let provider_with_wallet = ProviderBuilder::new()
             .with_recommended_fillers()
             .wallet(wallet.clone())
-            .on_http(format!("https://sepolia.base.org").parse().unwrap());
+            .on_http(self.rpc_url.clone());
         
         let contract = Clearinghouse402::new(self.clearinghouse_address, &provider_with_wallet);
         
@@ -188,19 +271,60 @@ impl BlockchainServiceAlloy {
             Bytes::from(public_values.to_vec()),
         );
         
-        let pending_tx = tx
-            .send()
-            .await
+        let pending_tx = self
+            .timed(tx.send())
+            .await?
             .map_err(|e| AppError::TransactionFailed(format!("Send failed: {}", e)))?;
         
-        let receipt = pending_tx
-            .get_receipt()
-            .await
+        let receipt = self
+            .timed(pending_tx.get_receipt())
+            .await?
             .map_err(|e| AppError::TransactionFailed(format!("Confirmation failed: {}", e)))?;
         
+        // Mined is not settled: a reverted tx still yields a receipt
+        let reason = match receipt.block_number {
+            // Receipts carry no revert data, so replay the call at the same block;
+            // a timed-out replay just leaves the reason unknown
+            Some(block) if !receipt.status() => self
+                .timed(tx.call().block(BlockId::number(block)))
+                .await
+                .ok()
+                .and_then(|call| call.err())
+                .map(|e| e.to_string()),
+            _ => None,
+        };
+        let tx_hash = format!("{:?}", receipt.transaction_hash);
+        receipt_outcome(receipt.status(), &tx_hash, reason.as_deref())
+            .inspect_err(|e| tracing::warn!("{}", e))?;
+        
         tracing::info!("Settlement confirmed: tx={:?}", receipt.transaction_hash);
         
-        Ok(format!("{:?}", receipt.transaction_hash))
+        Ok(SettlementReceipt {
+            tx_hash,
+            block_number: receipt.block_number.ok_or_else(|| {
+                AppError::BlockchainError("Settlement receipt has no block number".to_string())
+            })?,
+        })
+    }
+    
+    /// Block a settlement transaction was included in, if it has been mined
+    pub async fn get_transaction_block(&self, tx_hash: &str) -> Result<Option<u64>, AppError> {
+        let hash: FixedBytes<32> = tx_hash
+            .parse()
+            .map_err(|_| AppError::BadRequest("Invalid transaction hash".to_string()))?;
+        
+        let receipt = self
+            .timed(self.provider.get_transaction_receipt(hash))
+            .await?
+            .map_err(|e| AppError::BlockchainError(e.to_string()))?;
+        
+        match receipt {
+            Some(receipt) => {
+                receipt_outcome(receipt.status(), tx_hash, None)?;
+                Ok(receipt.block_number)
+            }
+            None => Ok(None),
+        }
     }
     
     /// Get agent verification status from contract
@@ -211,27 +335,79 @@ impl BlockchainServiceAlloy {
         
         let contract = Clearinghouse402::new(self.clearinghouse_address, &*self.provider);
         
-        let verified = contract.isAgentVerified(agent)
-            .call()
-            .await
-            .unwrap_or_default()
+        // A failed read is an error, not "not verified"
+        let verified = self
+            .timed(contract.isAgentVerified(agent).call())
+            .await?
+            .map_err(|e| AppError::BlockchainError(format!("isAgentVerified() failed: {}", e)))?
             ._0;
         
-        let verified_until = contract.agentVerifiedUntil(agent)
-            .call()
-            .await
-            .map(|r| r._0.try_into().ok())
-            .unwrap_or(None);
+        let verified_until: u64 = self
+            .timed(contract.agentVerifiedUntil(agent).call())
+            .await?
+            .map_err(|e| AppError::BlockchainError(format!("agentVerifiedUntil() failed: {}", e)))?
+            ._0
+            .try_into()
+            .map_err(|_| AppError::BlockchainError("agentVerifiedUntil() out of range".to_string()))?;
         
         Ok(AgentStatus {
             address: address.to_string(),
             verified,
-            verified_until,
-            total_settlements: 0, // Would query from events in production
+            verified_until: Some(verified_until),
+            total_settlements: 0, // Filled in from the settlement indexer
             total_volume_usdc: 0,
         })
     }
     
+    /// Get `Settlement` events in the inclusive block range
+    pub async fn get_settlement_logs(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<SettlementLog>, AppError> {
+        let filter = Filter::new()
+            .address(self.clearinghouse_address)
+            .event_signature(Clearinghouse402::Settlement::SIGNATURE_HASH)
+            .from_block(from_block)
+            .to_block(to_block);
+        
+        let logs = self
+            .timed(self.provider.get_logs(&filter))
+            .await?
+            .map_err(|e| AppError::BlockchainError(e.to_string()))?;
+        
+        logs.into_iter()
+            .map(|log| {
+                // Only pending logs lack these, and the range is mined blocks
+                let missing = |field: &str| {
+                    AppError::BlockchainError(format!("Settlement log has no {}", field))
+                };
+                let block_number = log.block_number.ok_or_else(|| missing("block number"))?;
+                let log_index = log.log_index.ok_or_else(|| missing("log index"))?;
+                let tx_hash = format!("{:?}", log.transaction_hash.ok_or_else(|| missing("tx hash"))?);
+                let event = log
+                    .log_decode::<Clearinghouse402::Settlement>()
+                    .map_err(|e| AppError::BlockchainError(format!("Bad Settlement log: {}", e)))?
+                    .inner
+                    .data;
+                
+                Ok(SettlementLog {
+                    block_number,
+                    log_index,
+                    tx_hash,
+                    agent: format!("{:?}", event.agent),
+                    asset: format!("{:?}", event.asset),
+                    amount: event.amount.try_into().map_err(|_| {
+                        AppError::BlockchainError("Settlement amount out of range".to_string())
+                    })?,
+                    price: event.price.try_into().map_err(|_| {
+                        AppError::BlockchainError("Settlement price out of range".to_string())
+                    })?,
+                })
+            })
+            .collect()
+    }
+    
     /// Check USDC balance and allowance for an agent
     pub async fn check_agent_funding(&self, agent_address: &str) -> Result<(u64, u64), AppError> {
         let agent: Address = agent_address
@@ -240,18 +416,93 @@ impl BlockchainServiceAlloy {
         
         let usdc = IERC20::new(self.usdc_address, &*self.provider);
         
-        let balance: u64 = usdc.balanceOf(agent)
-            .call()
-            .await
-            .map(|r| r._0.try_into().unwrap_or(0))
-            .unwrap_or(0);
+        // Amounts beyond u64 saturate: they cover any price we can quote
+        let balance = self
+            .timed(usdc.balanceOf(agent).call())
+            .await?
+            .map_err(|e| AppError::BlockchainError(format!("balanceOf() failed: {}", e)))?
+            ._0
+            .saturating_to::<u64>();
         
-        let allowance: u64 = usdc.allowance(agent, self.clearinghouse_address)
-            .call()
-            .await
-            .map(|r| r._0.try_into().unwrap_or(0))
-            .unwrap_or(0);
+        let allowance = self
+            .timed(usdc.allowance(agent, self.clearinghouse_address).call())
+            .await?
+            .map_err(|e| AppError::BlockchainError(format!("allowance() failed: {}", e)))?
+            ._0
+            .saturating_to::<u64>();
         
         Ok((balance, allowance))
     }
 }
+
+/// Map a mined receipt's status to a settlement outcome
+///
+/// Status 0 means the transaction reverted: it was mined, but nothing settled.
+fn receipt_outcome(status: bool, tx_hash: &str, reason: Option<&str>) -> Result<(), AppError> {
+    if status {
+        return Ok(());
+    }
+    Err(AppError::TransactionFailed(format!(
+        "Settlement reverted: tx={}, reason={}",
+        tx_hash,
+        reason.unwrap_or("unknown")
+    )))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::registry::InMemoryAssetRegistry;
+
+    /// A service whose RPC endpoint refuses every connection
+    async fn unreachable_service() -> BlockchainServiceAlloy {
+        let mut config = Config::from_env().unwrap();
+        config.rpc_url = "http://127.0.0.1:1".to_string();
+        config.rpc_timeout_secs = 5;
+        let registry = Arc::new(InMemoryAssetRegistry::with_defaults());
+        BlockchainServiceAlloy::new(&config, registry).await.unwrap()
+    }
+
+    #[tokio::test]
+    async fn failed_verification_read_is_an_error_not_unverified() {
+        let service = unreachable_service().await;
+        match service.get_agent_status("0x70997970C51812dc3A010C7d01b50e0d17dc79C8").await {
+            Err(AppError::BlockchainError(message)) => {
+                assert!(message.starts_with("isAgentVerified() failed"), "{}", message)
+            }
+            other => panic!("expected BlockchainError, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn failed_reads_are_not_masked() {
+        let service = unreachable_service().await;
+        assert!(matches!(
+            service.get_listed_assets().await,
+            Err(AppError::BlockchainError(_))
+        ));
+        assert!(matches!(
+            service.check_agent_funding("0x70997970C51812dc3A010C7d01b50e0d17dc79C8").await,
+            Err(AppError::BlockchainError(_))
+        ));
+    }
+
+    #[test]
+    fn successful_receipt_is_settled() {
+        receipt_outcome(true, "0xabc", None).unwrap();
+    }
+
+    #[test]
+    fn reverted_receipt_is_a_failed_transaction() {
+        match receipt_outcome(false, "0xabc", Some("QuoteExpired()")) {
+            Err(AppError::TransactionFailed(message)) => {
+                assert_eq!(message, "Settlement reverted: tx=0xabc, reason=QuoteExpired()")
+            }
+            other => panic!("expected a failed transaction, got {:?}", other),
+        }
+        assert!(matches!(
+            receipt_outcome(false, "0xabc", None),
+            Err(AppError::TransactionFailed(message)) if message.ends_with("reason=unknown")
+        ));
+    }
+}
@@ -0,0 +1,342 @@
+//! Mock chain the server runs against by default
+
+use std::future::Future;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::models::{AgentStatus, Asset};
+use crate::services::blockchain::{SettlementReceipt, SettlementSimulation};
+use crate::services::indexer::SettlementLog;
+use crate::services::registry::{find_asset, AssetRegistry};
+use crate::services::verifier::decode_public_values;
+
+/// Mock chain head at startup
+const MOCK_BLOCK_NUMBER: u64 = 12345678;
+
+/// Mock block time, matching Base
+const MOCK_BLOCK_TIME_SECS: u64 = 2;
+
+/// Gas the mock chain reports for `settle(...)`
+const MOCK_SETTLE_GAS: u64 = 180_000;
+
+/// Service for interacting with Base blockchain
+pub struct BlockchainService {
+    // In production: ethers::Provider, wallet, contract instances
+    
+    /// Listed assets
+    registry: Arc<dyn AssetRegistry>,
+    
+    /// Mock contract fee
+    fee_bps: u64,
+    
+    /// Settlement events emitted by mock settlements
+    settlement_logs: Mutex<Vec<SettlementLog>>,
+    
+    /// Mock chain advances one block every MOCK_BLOCK_TIME_SECS from here
+    started: Instant,
+    
+    /// Bound on every chain read (`RPC_TIMEOUT_SECS`)
+    rpc_timeout: Duration,
+    
+    /// Mock USDC (balance, allowance) of every agent
+    agent_funding: (u64, u64),
+    
+    /// Latency added in front of every mock RPC, to stand in for a stalled node
+    stall: Duration,
+}
+
+impl BlockchainService {
+    pub async fn new(config: &Config, registry: Arc<dyn AssetRegistry>) -> anyhow::Result<Self> {
+        Ok(Self {
+            registry,
+            fee_bps: config.default_fee_bps,
+            settlement_logs: Mutex::new(Vec::new()),
+            started: Instant::now(),
+            rpc_timeout: Duration::from_secs(config.rpc_timeout_secs),
+            // Mock agents are always funded
+            agent_funding: (u64::MAX, u64::MAX),
+            stall: Duration::ZERO,
+        })
+    }
+    
+    /// Fund every mock agent with this USDC balance and clearinghouse allowance
+    #[cfg(test)]
+    pub fn set_agent_funding(&mut self, balance: u64, allowance: u64) {
+        self.agent_funding = (balance, allowance);
+    }
+    
+    /// Set the fee the mock contract's `feeBps()` returns
+    #[cfg(test)]
+    pub fn set_fee_bps(&mut self, fee_bps: u64) {
+        self.fee_bps = fee_bps;
+    }
+    
+    /// Make every mock RPC take this long before answering
+    #[cfg(test)]
+    pub fn set_stall(&mut self, stall: Duration) {
+        self.stall = stall;
+    }
+    
+    /// Bound an RPC call by `RPC_TIMEOUT_SECS` so a hung connection can't stall a worker
+    async fn timed<T>(
+        &self,
+        call: impl Future<Output = Result<T, AppError>>,
+    ) -> Result<T, AppError> {
+        let stalled = async {
+            tokio::time::sleep(self.stall).await;
+            call.await
+        };
+        tokio::time::timeout(self.rpc_timeout, stalled)
+            .await
+            .map_err(|_| AppError::BlockchainError("rpc timeout".to_string()))?
+    }
+    
+    /// Get current block number
+    pub async fn get_block_number(&self) -> Result<u64, AppError> {
+        // In production: self.provider.get_block_number().await
+        // Mock for demo
+        self.timed(async {
+            Ok(MOCK_BLOCK_NUMBER + self.started.elapsed().as_secs() / MOCK_BLOCK_TIME_SECS)
+        })
+        .await
+    }
+    
+    /// Get the clearinghouse fee in basis points
+    pub async fn get_fee_bps(&self) -> Result<u64, AppError> {
+        // In production: clearinghouse.feeBps()
+        self.timed(async { Ok(self.fee_bps) }).await
+    }
+    
+    /// Get all listed assets from clearinghouse
+    pub async fn get_listed_assets(&self) -> Result<Vec<Asset>, AppError> {
+        // In production: refresh registry entries from the contract
+        self.timed(async { Ok(self.registry.list()) }).await
+    }
+    
+    /// Get specific asset details
+    pub async fn get_asset(&self, asset_id: &str) -> Result<Option<Asset>, AppError> {
+        let assets = self.get_listed_assets().await?;
+        find_asset(assets, asset_id)
+    }
+    
+    /// Simulate settlement without sending a transaction
+    ///
+    /// Mirrors the contract's own checks in `settle(...)`, in order.
+    pub async fn simulate_settlement(
+        &self,
+        asset_address: &str,
+        amount: u64,
+        quote_expiry: u64,
+        compliance_proof: &[u8],
+        public_values: &[u8],
+    ) -> Result<SettlementSimulation, AppError> {
+        // In production: clearinghouse.settle(...).call() and .estimate_gas()
+        self.timed(async {
+            let now = std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_secs();
+            let listed = self
+                .registry
+                .list()
+                .iter()
+                .any(|a| a.active && a.address.eq_ignore_ascii_case(asset_address));
+            let proof_ok = !compliance_proof.is_empty()
+                && decode_public_values(public_values).is_some_and(|pv| pv.valid_until > now);
+            
+            let revert_reason = if !listed {
+                Some("InvalidAsset()")
+            } else if now > quote_expiry {
+                Some("QuoteExpired()")
+            } else if !proof_ok {
+                Some("InvalidProof()")
+            } else {
+                None
+            };
+            
+            tracing::debug!(
+                "Simulated settlement: asset={}, amount={}, revert={:?}",
+                asset_address,
+                amount,
+                revert_reason
+            );
+            
+            Ok(SettlementSimulation {
+                gas_estimate: revert_reason.is_none().then_some(MOCK_SETTLE_GAS),
+                revert_reason: revert_reason.map(str::to_string),
+            })
+        })
+        .await
+    }
+    
+    /// Execute settlement on-chain
+    pub async fn execute_settlement(
+        &self,
+        asset_address: &str,
+        amount: u64,
+        quote_expiry: u64,
+        compliance_proof: &[u8],
+        public_values: &[u8],
+    ) -> Result<SettlementReceipt, AppError> {
+        tracing::info!(
+            "Executing settlement: asset={}, amount={}, expiry={}, proof_len={}, values_len={}",
+            asset_address,
+            amount,
+            quote_expiry,
+            compliance_proof.len(),
+            public_values.len()
+        );
+        
+        // In production:
+        // 1. Build transaction data
+        // 2. Estimate gas
+        // 3. Send transaction
+        // 4. Wait for confirmation
+        
+        /*
+        let calldata = clearinghouse_contract
+            .settle(
+                asset_address.parse()?,
+                amount.into(),
+                quote_expiry.into(),
+                compliance_proof.into(),
+                public_values.into(),
+            )
+            .calldata();
+        
+        let tx = wallet
+            .send_transaction(tx_request, None)
+            .await?
+            .await?;
+        */
+        
+        // Mock response for demo
+        let mock_tx_hash = format!(
+            "0x{:064x}",
+            std::time::SystemTime::now()
+                .duration_since(std::time::UNIX_EPOCH)
+                .unwrap()
+                .as_nanos()
+        );
+        
+        // Emit the Settlement event the contract would log
+        let agent = public_values
+            .get(12..32)
+            .map(|addr| format!("0x{}", hex::encode(addr)))
+            .unwrap_or_default();
+        let price_per_unit = self
+            .get_asset(asset_address)
+            .await?
+            .map_or(0, |a| a.price_per_unit);
+        let block_number = self.get_block_number().await?;
+        {
+            let mut logs = self.settlement_logs.lock().unwrap();
+            let log_index = logs.len() as u64;
+            logs.push(SettlementLog {
+                block_number,
+                log_index,
+                tx_hash: mock_tx_hash.clone(),
+                agent,
+                asset: asset_address.to_string(),
+                amount,
+                price: amount * price_per_unit,
+            });
+        }
+        
+        tracing::info!("Settlement complete: tx={}", mock_tx_hash);
+        
+        Ok(SettlementReceipt {
+            tx_hash: mock_tx_hash,
+            block_number,
+        })
+    }
+    
+    /// Block a settlement transaction was included in, if it has been mined
+    pub async fn get_transaction_block(&self, tx_hash: &str) -> Result<Option<u64>, AppError> {
+        // In production: eth_getTransactionReceipt
+        self.timed(async {
+            let logs = self.settlement_logs.lock().unwrap();
+            Ok(logs
+                .iter()
+                .find(|l| l.tx_hash.eq_ignore_ascii_case(tx_hash))
+                .map(|l| l.block_number))
+        })
+        .await
+    }
+    
+    /// Get agent verification status
+    pub async fn get_agent_status(&self, address: &str) -> Result<AgentStatus, AppError> {
+        // In production: query clearinghouse.agentVerifiedUntil(address)
+        self.timed(async {
+            Ok(AgentStatus {
+                address: address.to_string(),
+                verified: false,
+                verified_until: None,
+                total_settlements: 0, // Filled in from the settlement indexer
+                total_volume_usdc: 0,
+            })
+        })
+        .await
+    }
+    
+    /// Check USDC balance and allowance for an agent
+    pub async fn check_agent_funding(&self, _agent_address: &str) -> Result<(u64, u64), AppError> {
+        // In production: usdc.balanceOf(agent), usdc.allowance(agent, clearinghouse)
+        self.timed(async { Ok(self.agent_funding) }).await
+    }
+    
+    /// Get `Settlement` events in the inclusive block range
+    pub async fn get_settlement_logs(
+        &self,
+        from_block: u64,
+        to_block: u64,
+    ) -> Result<Vec<SettlementLog>, AppError> {
+        // In production: eth_getLogs filtered on the Settlement topic
+        self.timed(async {
+            let logs = self.settlement_logs.lock().unwrap();
+            Ok(logs
+                .iter()
+                .filter(|l| (from_block..=to_block).contains(&l.block_number))
+                .cloned()
+                .collect())
+        })
+        .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::services::registry::InMemoryAssetRegistry;
+
+    async fn service(rpc_timeout: Duration) -> BlockchainService {
+        let config = Config::from_env().unwrap();
+        let registry = Arc::new(InMemoryAssetRegistry::with_defaults());
+        let mut service = BlockchainService::new(&config, registry).await.unwrap();
+        service.rpc_timeout = rpc_timeout;
+        service
+    }
+
+    #[tokio::test]
+    async fn rpc_call_past_the_timeout_fails() {
+        let service = service(Duration::from_millis(20)).await;
+        let slow_rpc = async {
+            tokio::time::sleep(Duration::from_secs(5)).await;
+            Ok(1u64)
+        };
+        match service.timed(slow_rpc).await {
+            Err(AppError::BlockchainError(message)) => assert_eq!(message, "rpc timeout"),
+            other => panic!("expected rpc timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn rpc_call_within_the_timeout_succeeds() {
+        let service = service(Duration::from_secs(1)).await;
+        assert!(service.get_block_number().await.unwrap() >= MOCK_BLOCK_NUMBER);
+    }
+}
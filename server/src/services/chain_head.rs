@@ -0,0 +1,53 @@
+//! Cached chain head
+//!
+//! A background poller keeps the latest block number so `/health` never
+//! waits on the RPC; a health-check storm costs one RPC call per poll.
+
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use tokio::time::Instant;
+
+use crate::services::blockchain::BlockchainService;
+
+/// How often the chain head is refreshed
+const POLL_INTERVAL: Duration = Duration::from_secs(3);
+
+/// Latest block number and when it was read
+#[derive(Default)]
+pub struct ChainHead {
+    latest: RwLock<Option<(u64, Instant)>>,
+}
+
+impl ChainHead {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Record a successfully read block number
+    pub fn record(&self, block_number: u64) {
+        *self.latest.write().unwrap() = Some((block_number, Instant::now()));
+    }
+
+    /// Last block number read and how long ago, if any read has succeeded
+    pub fn latest(&self) -> Option<(u64, Duration)> {
+        self.latest
+            .read()
+            .unwrap()
+            .map(|(block_number, read_at)| (block_number, read_at.elapsed()))
+    }
+
+    /// Refresh the head in the background; failures leave the cache to age
+    pub fn spawn(self: Arc<Self>, blockchain: Arc<BlockchainService>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                match blockchain.get_block_number().await {
+                    Ok(block_number) => self.record(block_number),
+                    Err(e) => tracing::warn!("Chain head poll failed: {}", e),
+                }
+            }
+        });
+    }
+}
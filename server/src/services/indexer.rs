@@ -0,0 +1,284 @@
+//! Settlement event indexer
+//!
+//! Follows `Settlement` events from the clearinghouse and aggregates per-agent
+//! settlement counts and volume. Logs newer than the finality depth are kept
+//! keyed by `(block, log_index)` and re-read on every poll, so a reorg simply
+//! replaces them; older logs are folded into the totals and pruned.
+//!
+//! Every log seen for the first time is also published as a
+//! `SettlementEvent` to live subscribers, with a short replay buffer.
+
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use serde::Serialize;
+use tokio::sync::broadcast;
+
+use crate::config::Config;
+use crate::error::AppError;
+use crate::services::blockchain::BlockchainService;
+
+/// How often the indexer polls for new blocks
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Events kept for `Last-Event-ID` replay
+const RECENT_EVENTS: usize = 256;
+
+/// A decoded `Settlement(agent, asset, amount, price, txId)` log
+#[derive(Debug, Clone, Serialize)]
+pub struct SettlementLog {
+    pub block_number: u64,
+    pub log_index: u64,
+    pub tx_hash: String,
+    pub agent: String,
+    pub asset: String,
+    pub amount: u64,
+    pub price: u64,
+}
+
+/// A settlement as published to live subscribers
+#[derive(Debug, Clone, Serialize)]
+pub struct SettlementEvent {
+    /// Monotonic per-process id, used as the SSE event id
+    pub id: u64,
+    #[serde(flatten)]
+    pub log: SettlementLog,
+}
+
+/// Aggregated settlement activity for one agent
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AgentTotals {
+    pub settlements: u64,
+    pub volume_usdc: u64,
+}
+
+impl AgentTotals {
+    fn add(&mut self, log: &SettlementLog) {
+        self.settlements += 1;
+        self.volume_usdc = self.volume_usdc.saturating_add(log.price);
+    }
+}
+
+#[derive(Default)]
+struct IndexState {
+    /// Totals from logs at or below `finalized_through`
+    finalized: HashMap<String, AgentTotals>,
+    /// Logs above `finalized_through`, still subject to reorgs
+    unfinalized: BTreeMap<(u64, u64), SettlementLog>,
+    /// Highest block whose logs have been folded into `finalized`
+    finalized_through: Option<u64>,
+    /// Most recent events, oldest first
+    recent: VecDeque<SettlementEvent>,
+    next_event_id: u64,
+}
+
+/// In-memory settlement index shared with the handlers
+pub struct SettlementIndexer {
+    start_block: u64,
+    finality_depth: u64,
+    state: RwLock<IndexState>,
+    events: broadcast::Sender<SettlementEvent>,
+}
+
+impl SettlementIndexer {
+    pub fn new(config: &Config) -> Self {
+        Self {
+            start_block: config.indexer_start_block,
+            finality_depth: config.finality_depth,
+            state: RwLock::new(IndexState::default()),
+            events: broadcast::channel(RECENT_EVENTS).0,
+        }
+    }
+
+    /// Settlement count and volume for an agent, including unfinalized logs
+    pub fn agent_totals(&self, agent: &str) -> AgentTotals {
+        let agent = agent.to_lowercase();
+        let state = self.state.read().unwrap();
+
+        let mut totals = state.finalized.get(&agent).copied().unwrap_or_default();
+        for log in state.unfinalized.values().filter(|l| l.agent == agent) {
+            totals.add(log);
+        }
+        totals
+    }
+
+    /// First block the next poll has to (re-)read
+    fn next_block(&self) -> u64 {
+        let state = self.state.read().unwrap();
+        state
+            .finalized_through
+            .map_or(self.start_block, |block| block + 1)
+    }
+
+    /// Replace every unfinalized log from `from_block` onwards with `logs`
+    pub fn apply(&self, from_block: u64, logs: Vec<SettlementLog>) {
+        let mut state = self.state.write().unwrap();
+
+        // Anything we previously saw in the re-read range may have been reorged out
+        let previous = std::mem::take(&mut state.unfinalized);
+        let (kept, reread): (BTreeMap<_, _>, BTreeMap<_, _>) = previous
+            .into_iter()
+            .partition(|&((block, _), _)| block < from_block);
+        state.unfinalized = kept;
+
+        for mut log in logs {
+            log.agent = log.agent.to_lowercase();
+            let key = (log.block_number, log.log_index);
+            if !reread.contains_key(&key) {
+                Self::publish(&mut state, &self.events, log.clone());
+            }
+            state.unfinalized.insert(key, log);
+        }
+    }
+
+    /// Record a newly seen log and send it to live subscribers
+    fn publish(state: &mut IndexState, events: &broadcast::Sender<SettlementEvent>, log: SettlementLog) {
+        let event = SettlementEvent {
+            id: state.next_event_id,
+            log,
+        };
+        state.next_event_id += 1;
+
+        if state.recent.len() == RECENT_EVENTS {
+            state.recent.pop_front();
+        }
+        state.recent.push_back(event.clone());
+        // No receivers is fine; the event stays in the replay buffer
+        let _ = events.send(event);
+    }
+
+    /// Buffered events after `last_event_id`, plus a receiver for new ones
+    ///
+    /// Both are taken under the same lock, so no event is missed or repeated.
+    pub fn subscribe(
+        &self,
+        last_event_id: Option<u64>,
+    ) -> (Vec<SettlementEvent>, broadcast::Receiver<SettlementEvent>) {
+        let state = self.state.read().unwrap();
+        let replay = match last_event_id {
+            Some(last) => state
+                .recent
+                .iter()
+                .filter(|event| event.id > last)
+                .cloned()
+                .collect(),
+            None => Vec::new(),
+        };
+        (replay, self.events.subscribe())
+    }
+
+    /// Fold logs at or below `block` into the finalized totals and prune them
+    pub fn finalize_through(&self, block: u64) {
+        let mut state = self.state.write().unwrap();
+        if state.finalized_through.is_some_and(|done| done >= block) {
+            return;
+        }
+
+        let pending = state.unfinalized.split_off(&(block + 1, 0));
+        let finalized = std::mem::replace(&mut state.unfinalized, pending);
+        for log in finalized.values() {
+            state.finalized.entry(log.agent.clone()).or_default().add(log);
+        }
+        state.finalized_through = Some(block);
+    }
+
+    /// Read the unfinalized range up to the chain head once
+    pub async fn poll(&self, blockchain: &BlockchainService) -> Result<(), AppError> {
+        let head = blockchain.get_block_number().await?;
+        let from_block = self.next_block();
+        if from_block > head {
+            return Ok(());
+        }
+
+        let logs = blockchain.get_settlement_logs(from_block, head).await?;
+        self.apply(from_block, logs);
+
+        if let Some(final_block) = head.checked_sub(self.finality_depth) {
+            if final_block >= self.start_block {
+                self.finalize_through(final_block);
+            }
+        }
+        Ok(())
+    }
+
+    /// Poll forever in the background
+    pub fn spawn(self: Arc<Self>, blockchain: Arc<BlockchainService>) {
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(POLL_INTERVAL);
+            loop {
+                interval.tick().await;
+                if let Err(e) = self.poll(&blockchain).await {
+                    tracing::warn!("Settlement indexer poll failed: {}", e);
+                }
+            }
+        });
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const AGENT: &str = "0x742d35Cc6634C0532925a3b844Bc9e7595f1Ab23";
+
+    fn indexer() -> SettlementIndexer {
+        SettlementIndexer::new(&Config::from_env().unwrap())
+    }
+
+    fn log(block_number: u64, log_index: u64, price: u64) -> SettlementLog {
+        SettlementLog {
+            block_number,
+            log_index,
+            tx_hash: format!("0x{:064x}", block_number * 100 + log_index),
+            agent: AGENT.to_string(),
+            asset: "TBILL-26".to_string(),
+            amount: 1,
+            price,
+        }
+    }
+
+    fn totals(indexer: &SettlementIndexer) -> (u64, u64) {
+        let totals = indexer.agent_totals(&AGENT.to_uppercase().replace("0X", "0x"));
+        (totals.settlements, totals.volume_usdc)
+    }
+
+    #[test]
+    fn reorged_logs_are_replaced_not_double_counted() {
+        let indexer = indexer();
+        indexer.apply(10, vec![log(10, 0, 100), log(11, 0, 200)]);
+        assert_eq!(totals(&indexer), (2, 300));
+
+        // Block 11 was reorged out and its settlement landed in block 12
+        indexer.apply(11, vec![log(12, 0, 200)]);
+        assert_eq!(totals(&indexer), (2, 300));
+        assert_eq!(indexer.next_block(), indexer.start_block);
+    }
+
+    #[test]
+    fn only_new_logs_are_published() {
+        let indexer = indexer();
+        indexer.apply(10, vec![log(10, 0, 100)]);
+        indexer.apply(10, vec![log(10, 0, 100), log(10, 1, 50)]);
+
+        let (replay, _) = indexer.subscribe(Some(0));
+        let published: Vec<_> = replay.iter().map(|e| (e.id, e.log.log_index)).collect();
+        assert_eq!(published, vec![(1, 1)]);
+    }
+
+    #[test]
+    fn finalized_logs_survive_later_rereads() {
+        let indexer = indexer();
+        indexer.apply(10, vec![log(10, 0, 100), log(12, 0, 200)]);
+        indexer.finalize_through(11);
+        assert_eq!(indexer.next_block(), 12);
+
+        // Re-reading from the next block leaves block 10 in the totals
+        indexer.apply(12, vec![]);
+        assert_eq!(totals(&indexer), (1, 100));
+
+        // Finalizing backwards is a no-op
+        indexer.finalize_through(5);
+        assert_eq!(indexer.next_block(), 12);
+    }
+}
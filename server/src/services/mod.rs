@@ -1,2 +1,12 @@
+pub mod auth;
 pub mod blockchain;
-// pub mod blockchain_alloy; // Needs alloy 0.9+ for production
+#[cfg(not(feature = "alloy"))]
+pub mod blockchain_mock;
+pub mod chain_head;
+pub mod indexer;
+pub mod quotes;
+pub mod registry;
+pub mod verifier;
+pub mod vk_registry;
+#[cfg(feature = "alloy")]
+pub mod blockchain_alloy;
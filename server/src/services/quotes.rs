@@ -0,0 +1,69 @@
+//! Issued quote store
+//!
+//! Remembers the terms of every quote and x402 challenge so a settlement can
+//! be held to what was actually quoted for its `quote_id`.
+
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+
+/// Terms of an issued quote
+#[derive(Debug, Clone)]
+pub struct StoredQuote {
+    pub asset_id: String,
+    pub amount: u64,
+    /// Price including fee, in atomic USDC
+    pub total_price: u64,
+    pub expiry: u64,
+}
+
+/// Storage for issued quotes; swap the in-memory impl for a shared one
+/// (e.g. Redis) when running several server instances
+///
+/// Methods are synchronous so an implementation's locks can never be held
+/// across an `.await` in a handler.
+pub trait QuoteStore: Send + Sync {
+    /// Record a quote, dropping any that expired before `now`
+    fn insert(&self, quote_id: &str, quote: StoredQuote, now: u64);
+
+    /// Terms of a quote, if it was issued and not yet pruned
+    fn get(&self, quote_id: &str) -> Option<StoredQuote>;
+}
+
+/// Quotes by id, held in process memory
+#[derive(Default)]
+pub struct InMemoryQuoteStore {
+    quotes: RwLock<HashMap<String, StoredQuote>>,
+}
+
+impl InMemoryQuoteStore {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl QuoteStore for InMemoryQuoteStore {
+    fn insert(&self, quote_id: &str, quote: StoredQuote, now: u64) {
+        let mut quotes = self.quotes.write().unwrap();
+        quotes.retain(|_, q| q.expiry >= now);
+        quotes.insert(quote_id.to_string(), quote);
+    }
+
+    fn get(&self, quote_id: &str) -> Option<StoredQuote> {
+        self.quotes.read().unwrap().get(quote_id).cloned()
+    }
+}
+
+/// Mutable state shared by the handlers, cheap to clone
+#[derive(Clone)]
+pub struct Stores {
+    pub quotes: Arc<dyn QuoteStore>,
+}
+
+impl Stores {
+    /// Stores held in process memory
+    pub fn in_memory() -> Self {
+        Self {
+            quotes: Arc::new(InMemoryQuoteStore::new()),
+        }
+    }
+}
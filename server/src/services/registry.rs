@@ -0,0 +1,225 @@
+//! Asset registry
+//!
+//! The set of assets the clearinghouse offers. The blockchain service reads
+//! listings from here, and operators manage them through the admin API.
+
+use std::sync::RwLock;
+
+use ethers::types::Address;
+
+use crate::error::AppError;
+use crate::models::Asset;
+
+/// Storage for listed assets; swap the in-memory impl for a persistent one
+pub trait AssetRegistry: Send + Sync {
+    /// All listed assets
+    fn list(&self) -> Vec<Asset>;
+
+    /// List a new asset
+    fn insert(&self, asset: Asset) -> Result<(), AppError>;
+
+    /// Delist an asset by id, returning it if it was listed
+    fn remove(&self, id: &str) -> Option<Asset>;
+}
+
+/// Registry held in process memory
+pub struct InMemoryAssetRegistry {
+    assets: RwLock<Vec<Asset>>,
+}
+
+impl InMemoryAssetRegistry {
+    pub fn new(assets: Vec<Asset>) -> Self {
+        Self {
+            assets: RwLock::new(assets),
+        }
+    }
+
+    /// Registry seeded with the demo T-Bill listing
+    pub fn with_defaults() -> Self {
+        Self::new(vec![Asset {
+            id: "TBILL-26".to_string(),
+            name: "Treasury Bill Oct 2026".to_string(),
+            symbol: "TBILL-26".to_string(),
+            address: "0x0cB59FaA219b80D8FbD28E9D37008f2db10F847A".to_string(),
+            issuer: "0xc7554F1B16ad0b3Ce363d53364C9817743E32f90".to_string(),
+            price_per_unit: 980_000, // $0.98 in atomic USDC
+            currency: "USDC".to_string(),
+            compliance_circuit: "0xDd2ffa97F680032332EA4905586e2366584Ae0be".to_string(),
+            active: true,
+            quote_validity_seconds: None,
+            min_amount: None,
+            max_amount: None,
+        }])
+    }
+}
+
+impl AssetRegistry for InMemoryAssetRegistry {
+    fn list(&self) -> Vec<Asset> {
+        self.assets.read().unwrap().clone()
+    }
+
+    fn insert(&self, asset: Asset) -> Result<(), AppError> {
+        validate_asset(&asset)?;
+
+        let mut assets = self.assets.write().unwrap();
+        if assets
+            .iter()
+            .any(|a| a.id == asset.id || a.address.eq_ignore_ascii_case(&asset.address))
+        {
+            return Err(AppError::BadRequest(format!("Asset already listed: {}", asset.id)));
+        }
+        assets.push(asset);
+        Ok(())
+    }
+
+    fn remove(&self, id: &str) -> Option<Asset> {
+        let mut assets = self.assets.write().unwrap();
+        let index = assets.iter().position(|a| a.id == id)?;
+        Some(assets.remove(index))
+    }
+}
+
+/// Find an asset by exact id, exact symbol, or case-insensitive address
+///
+/// An unknown key that isn't an address either is a client error rather
+/// than a missing asset.
+pub fn find_asset(assets: Vec<Asset>, key: &str) -> Result<Option<Asset>, AppError> {
+    let address = key.parse::<Address>().ok();
+
+    let found = assets.into_iter().find(|a| {
+        a.id == key
+            || a.symbol == key
+            || address.is_some_and(|addr| a.address.parse::<Address>().ok() == Some(addr))
+    });
+
+    match (found, address) {
+        (Some(asset), _) => Ok(Some(asset)),
+        (None, Some(_)) => Ok(None),
+        (None, None) => Err(AppError::BadRequest(format!(
+            "Not a listed asset id, symbol or address: {}",
+            key
+        ))),
+    }
+}
+
+/// Check a listing is well-formed before it is accepted
+pub fn validate_asset(asset: &Asset) -> Result<(), AppError> {
+    if asset.id.trim().is_empty() {
+        return Err(AppError::BadRequest("Asset id is required".to_string()));
+    }
+
+    // These are echoed in x402 challenge headers
+    let echoed = [("id", &asset.id), ("symbol", &asset.symbol), ("currency", &asset.currency)];
+    for (field, value) in echoed {
+        if !is_token(value) {
+            return Err(AppError::BadRequest(format!(
+                "Asset {} must be ASCII letters, digits, '-', '_' or '.': {:?}",
+                field, value
+            )));
+        }
+    }
+
+    if asset.price_per_unit == 0 {
+        return Err(AppError::BadRequest("price_per_unit must be positive".to_string()));
+    }
+
+    if let (Some(min), Some(max)) = (asset.min_amount, asset.max_amount) {
+        if min > max {
+            return Err(AppError::BadRequest(format!(
+                "min_amount {} exceeds max_amount {}",
+                min, max
+            )));
+        }
+    }
+
+    asset
+        .address
+        .parse::<Address>()
+        .map_err(|_| AppError::BadRequest(format!("Invalid asset address: {}", asset.address)))?;
+
+    // Circuit ids are either a verifier address or a 32-byte verification key hash
+    let circuit = asset
+        .compliance_circuit
+        .strip_prefix("0x")
+        .and_then(|hex_id| hex::decode(hex_id).ok());
+    if !matches!(circuit.as_deref().map(<[u8]>::len), Some(20 | 32)) {
+        return Err(AppError::BadRequest(format!(
+            "Invalid compliance circuit: {}",
+            asset.compliance_circuit
+        )));
+    }
+
+    Ok(())
+}
+
+/// Non-empty and limited to characters that are safe in a header value
+fn is_token(value: &str) -> bool {
+    !value.is_empty()
+        && value
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '-' | '_' | '.'))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tbill() -> Asset {
+        InMemoryAssetRegistry::with_defaults().list().remove(0)
+    }
+
+    #[test]
+    fn default_listing_is_valid() {
+        validate_asset(&tbill()).unwrap();
+    }
+
+    #[test]
+    fn header_unsafe_id_symbol_or_currency_is_rejected() {
+        for (id, symbol, currency) in [
+            ("T BILL", "TBILL-26", "USDC"),
+            ("TBILL-26", "T\u{e9}BILL", "USDC"),
+            ("TBILL-26", "", "USDC"),
+            ("TBILL-26", "TBILL-26", "USD\r\nC"),
+        ] {
+            let asset = Asset {
+                id: id.to_string(),
+                symbol: symbol.to_string(),
+                currency: currency.to_string(),
+                ..tbill()
+            };
+            assert!(
+                matches!(validate_asset(&asset), Err(AppError::BadRequest(_))),
+                "{:?} / {:?} / {:?}",
+                id,
+                symbol,
+                currency
+            );
+        }
+    }
+
+    #[test]
+    fn registry_refuses_header_unsafe_listing() {
+        let registry = InMemoryAssetRegistry::new(vec![]);
+        let asset = Asset {
+            id: "TBILL\"26".to_string(),
+            ..tbill()
+        };
+        assert!(registry.insert(asset).is_err());
+        assert!(registry.list().is_empty());
+    }
+
+    #[test]
+    fn finds_by_id_symbol_or_any_case_address() {
+        let listed = || vec![Asset { symbol: "TB26".to_string(), ..tbill() }];
+        for key in ["TBILL-26", "TB26", "0x0cb59faa219b80d8fbd28e9d37008f2db10f847a"] {
+            assert_eq!(find_asset(listed(), key).unwrap().unwrap().id, "TBILL-26", "{}", key);
+        }
+    }
+
+    #[test]
+    fn unknown_address_is_missing_but_unknown_name_is_a_bad_request() {
+        let unlisted = "0x000000000000000000000000000000000000dEaD";
+        assert!(find_asset(vec![tbill()], unlisted).unwrap().is_none());
+        assert!(matches!(find_asset(vec![tbill()], "tbill-26"), Err(AppError::BadRequest(_))));
+    }
+}
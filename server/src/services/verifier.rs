@@ -0,0 +1,240 @@
+//! Off-chain compliance proof verification
+//!
+//! Lets integrators check a proof against a registered circuit without
+//! settling. The committed public values are decoded and checked in full, but
+//! SP1 verification of the proof bytes only happens on-chain for now, so no
+//! proof is ever reported valid here.
+
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use ethers::types::Address;
+
+use crate::error::AppError;
+use crate::models::CommittedValues;
+use crate::services::vk_registry::VkRegistry;
+
+/// ABI-encoded `(address agent, uint256 validUntil, bytes32 jurisdictionHash)`
+const PUBLIC_VALUES_LEN: usize = 3 * 32;
+
+/// The same, followed by `uint256 currentTime`. No circuit commits this yet:
+/// `PublicOutputs` in circuits/ ends at the identity commitment.
+const TIMED_PUBLIC_VALUES_LEN: usize = 4 * 32;
+
+/// Outcome of checking a proof off-chain
+#[derive(Debug)]
+pub enum Verification {
+    /// Public values are well-formed and current; the proof bytes were not checked
+    Unchecked(CommittedValues),
+    /// Rejected before any proof verification, with the reason
+    Rejected(&'static str),
+}
+
+/// Verifies compliance proofs against registered circuits
+pub struct ProofVerifier {
+    vk_registry: Arc<VkRegistry>,
+    /// Allowed skew between a proof's committed time and ours
+    current_time_tolerance_secs: u64,
+}
+
+impl ProofVerifier {
+    pub fn new(vk_registry: Arc<VkRegistry>, current_time_tolerance_secs: u64) -> Self {
+        Self {
+            vk_registry,
+            current_time_tolerance_secs,
+        }
+    }
+
+    /// Check everything about a proof short of the SP1 verification itself
+    ///
+    /// Fails only for an unknown circuit; a bad proof is `Rejected`.
+    pub fn verify(
+        &self,
+        circuit_id: &str,
+        proof: &[u8],
+        public_values: &[u8],
+    ) -> Result<Verification, AppError> {
+        self.vk_registry.get(circuit_id)?;
+
+        if proof.is_empty() {
+            return Ok(Verification::Rejected("empty proof"));
+        }
+        let Some(committed) = decode_public_values(public_values) else {
+            return Ok(Verification::Rejected("malformed public values"));
+        };
+
+        let now = unix_now();
+        if committed.valid_until <= now {
+            return Ok(Verification::Rejected("verification expired"));
+        }
+        if self.check_current_time(&committed, now).is_err() {
+            return Ok(Verification::Rejected("committed time outside tolerance"));
+        }
+
+        Ok(Verification::Unchecked(committed))
+    }
+
+    /// Reject public values whose committed proving time is outside the window
+    ///
+    /// Values without a committed time (or that don't decode) are left to the
+    /// on-chain verifier.
+    pub fn check_freshness(&self, public_values: &[u8]) -> Result<(), AppError> {
+        match decode_public_values(public_values) {
+            Some(committed) => self.check_current_time(&committed, unix_now()),
+            None => Ok(()),
+        }
+    }
+
+    fn check_current_time(&self, committed: &CommittedValues, now: u64) -> Result<(), AppError> {
+        match committed.current_time {
+            Some(proven_at) if proven_at.abs_diff(now) > self.current_time_tolerance_secs => {
+                Err(AppError::InvalidProof)
+            }
+            _ => Ok(()),
+        }
+    }
+}
+
+fn unix_now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Decode the circuit's public values, rejecting non-canonical encodings
+pub fn decode_public_values(public_values: &[u8]) -> Option<CommittedValues> {
+    if !matches!(public_values.len(), PUBLIC_VALUES_LEN | TIMED_PUBLIC_VALUES_LEN) {
+        return None;
+    }
+
+    let mut words = public_values.chunks_exact(32);
+    let agent_word = words.next()?;
+    let valid_until = decode_u64_word(words.next()?)?;
+    let jurisdiction_hash = words.next()?;
+    let current_time = match words.next() {
+        Some(word) => Some(decode_u64_word(word)?),
+        None => None,
+    };
+
+    // Addresses must be left-padded with zeros
+    if agent_word[..12].iter().any(|&b| b != 0) {
+        return None;
+    }
+    let agent = Address::from_slice(&agent_word[12..]);
+
+    Some(CommittedValues {
+        agent_address: format!("{:?}", agent),
+        valid_until,
+        jurisdiction_hash: format!("0x{}", hex::encode(jurisdiction_hash)),
+        current_time,
+    })
+}
+
+/// A `uint256` word holding a `u64`
+fn decode_u64_word(word: &[u8]) -> Option<u64> {
+    if word[..24].iter().any(|&b| b != 0) {
+        return None;
+    }
+    Some(u64::from_be_bytes(word[24..].try_into().ok()?))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CIRCUIT: &str = "0xabc";
+
+    fn verifier() -> ProofVerifier {
+        let mut registry = VkRegistry::default();
+        registry.register(CIRCUIT, &[1, 2, 3]);
+        ProofVerifier::new(Arc::new(registry), 120)
+    }
+
+    fn word(value: u64) -> [u8; 32] {
+        let mut word = [0u8; 32];
+        word[24..].copy_from_slice(&value.to_be_bytes());
+        word
+    }
+
+    fn public_values(valid_until: u64) -> Vec<u8> {
+        let mut agent = [0u8; 32];
+        agent[12..].copy_from_slice(&[0x11; 20]);
+        [agent, word(valid_until), [0x22; 32]].concat()
+    }
+
+    #[test]
+    fn arbitrary_proof_bytes_are_never_reported_valid() {
+        let result = verifier()
+            .verify(CIRCUIT, b"not a proof", &public_values(unix_now() + 3600))
+            .unwrap();
+        match result {
+            Verification::Unchecked(committed) => {
+                assert_eq!(committed.agent_address, format!("{:?}", Address::repeat_byte(0x11)));
+            }
+            other => panic!("expected Unchecked, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn rejects_empty_proof_and_expired_values() {
+        let verifier = verifier();
+        assert!(matches!(
+            verifier.verify(CIRCUIT, b"", &public_values(unix_now() + 3600)).unwrap(),
+            Verification::Rejected("empty proof")
+        ));
+        assert!(matches!(
+            verifier.verify(CIRCUIT, b"proof", &public_values(unix_now() - 1)).unwrap(),
+            Verification::Rejected("verification expired")
+        ));
+    }
+
+    #[test]
+    fn unknown_circuit_is_an_error() {
+        let result = verifier().verify("0xdef", b"proof", &public_values(unix_now() + 3600));
+        assert!(matches!(result, Err(AppError::CircuitNotFound(_))));
+    }
+
+    #[test]
+    fn decodes_three_and_four_word_layouts() {
+        let committed = decode_public_values(&public_values(1_700_000_000)).unwrap();
+        assert_eq!(committed.valid_until, 1_700_000_000);
+        assert_eq!(committed.jurisdiction_hash, format!("0x{}", "22".repeat(32)));
+        assert_eq!(committed.current_time, None);
+
+        let timed = [public_values(1_700_000_000), word(1_690_000_000).to_vec()].concat();
+        assert_eq!(decode_public_values(&timed).unwrap().current_time, Some(1_690_000_000));
+    }
+
+    #[test]
+    fn committed_time_must_be_within_tolerance() {
+        let verifier = verifier();
+        let at = |current_time| CommittedValues {
+            agent_address: String::new(),
+            valid_until: 0,
+            jurisdiction_hash: String::new(),
+            current_time,
+        };
+        let now = 1_700_000_000;
+
+        verifier.check_current_time(&at(None), now).unwrap();
+        verifier.check_current_time(&at(Some(now - 120)), now).unwrap();
+        verifier.check_current_time(&at(Some(now + 120)), now).unwrap();
+        assert!(verifier.check_current_time(&at(Some(now - 121)), now).is_err());
+        assert!(verifier.check_current_time(&at(Some(now + 121)), now).is_err());
+    }
+
+    #[test]
+    fn rejects_non_canonical_public_values() {
+        let mut dirty_address = public_values(1);
+        dirty_address[0] = 1;
+        let mut wide_valid_until = public_values(1);
+        wide_valid_until[32] = 1;
+        let short = public_values(1)[..95].to_vec();
+        let five_words = [public_values(1), word(1).to_vec(), word(1).to_vec()].concat();
+
+        for values in [dirty_address, wide_valid_until, short, five_words, vec![]] {
+            assert!(decode_public_values(&values).is_none(), "{}", hex::encode(&values));
+        }
+    }
+}
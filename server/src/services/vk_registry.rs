@@ -0,0 +1,116 @@
+//! Compliance circuit verification keys
+//!
+//! Maps each `compliance_circuit` id to the SP1 verification key its proofs
+//! are checked against. Keys are loaded once at startup from `VK_DIR`, one
+//! file per circuit named after the circuit id (an optional `.vk` extension
+//! is ignored).
+
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+use anyhow::Context;
+use sha2::{Digest, Sha256};
+
+use crate::error::AppError;
+
+/// Demo circuit served when no key directory is configured
+const DEMO_CIRCUIT_ID: &str = "0xDd2ffa97F680032332EA4905586e2366584Ae0be";
+
+/// A registered circuit's SP1 verification key
+#[derive(Debug, Clone)]
+pub struct VerificationKey {
+    /// `0x`-prefixed SHA-256 of the key file; `None` for the demo circuit's
+    /// placeholder, which has no real key behind it
+    pub hash: Option<String>,
+}
+
+impl VerificationKey {
+    fn new(bytes: &[u8]) -> Self {
+        let hash = format!("0x{}", hex::encode(Sha256::digest(bytes)));
+        Self { hash: Some(hash) }
+    }
+}
+
+/// Verification keys by circuit id (case-insensitive)
+#[derive(Debug, Default)]
+pub struct VkRegistry {
+    keys: HashMap<String, VerificationKey>,
+}
+
+impl VkRegistry {
+    /// Load every key in `dir`, or the demo key if no directory is configured
+    pub fn load(dir: Option<&Path>) -> anyhow::Result<Self> {
+        let Some(dir) = dir else {
+            tracing::warn!("VK_DIR not set, registering a placeholder key for the demo circuit");
+            return Ok(Self::with_defaults());
+        };
+
+        let mut registry = Self::default();
+        let entries = fs::read_dir(dir)
+            .with_context(|| format!("Cannot read VK_DIR {}", dir.display()))?;
+        for entry in entries {
+            let path = entry?.path();
+            if !path.is_file() {
+                continue;
+            }
+            let circuit_id = path
+                .file_stem()
+                .and_then(|stem| stem.to_str())
+                .with_context(|| format!("Invalid key file name {}", path.display()))?;
+            let bytes = fs::read(&path)
+                .with_context(|| format!("Cannot read key file {}", path.display()))?;
+            registry.register(circuit_id, &bytes);
+        }
+
+        tracing::info!("Loaded {} verification key(s) from {}", registry.keys.len(), dir.display());
+        Ok(registry)
+    }
+
+    /// Registry accepting the demo circuit, without a real key for it
+    pub fn with_defaults() -> Self {
+        let mut registry = Self::default();
+        registry
+            .keys
+            .insert(DEMO_CIRCUIT_ID.to_lowercase(), VerificationKey { hash: None });
+        registry
+    }
+
+    /// Register (or replace) the key for a circuit
+    pub fn register(&mut self, circuit_id: &str, bytes: &[u8]) {
+        self.keys
+            .insert(circuit_id.to_lowercase(), VerificationKey::new(bytes));
+    }
+
+    /// Key for a circuit, if one is registered
+    pub fn find(&self, circuit_id: &str) -> Option<&VerificationKey> {
+        self.keys.get(&circuit_id.to_lowercase())
+    }
+
+    /// Key for a circuit, or 404 if none is registered
+    pub fn get(&self, circuit_id: &str) -> Result<&VerificationKey, AppError> {
+        self.find(circuit_id)
+            .ok_or_else(|| AppError::CircuitNotFound(circuit_id.to_string()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn demo_placeholder_has_no_key_hash() {
+        let registry = VkRegistry::with_defaults();
+        let key = registry.get(&DEMO_CIRCUIT_ID.to_uppercase()).unwrap();
+        assert_eq!(key.hash, None);
+    }
+
+    #[test]
+    fn registered_key_is_hashed() {
+        let mut registry = VkRegistry::default();
+        registry.register("0xABC", b"key");
+        let expected = format!("0x{}", hex::encode(Sha256::digest(b"key")));
+        assert_eq!(registry.find("0xabc").unwrap().hash.as_deref(), Some(expected.as_str()));
+        assert!(matches!(registry.get("0xdef"), Err(AppError::CircuitNotFound(_))));
+    }
+}
@@ -90,7 +90,7 @@ class MockProver:
         public_values = (
             bytes(12) + address_bytes +  # address padded to 32 bytes
             valid_until.to_bytes(32, 'big') +  # valid_until
-            hashlib.sha256(b"US_JURISDICTION").digest()  # jurisdiction hash
+            hashlib.sha256(b"US" + b"X402_JURISDICTION_V1").digest()  # jurisdiction hash
         )
         
         return proof_data, public_values
@@ -113,6 +113,7 @@ class AutonomousAgent:
         })
         
         # Wallet setup
+        self.private_key = None
         if wallet_address:
             self.wallet_address = wallet_address
         elif private_key and WEB3_AVAILABLE:
@@ -122,7 +123,6 @@ class AutonomousAgent:
         else:
             # Demo wallet
             self.wallet_address = "0x742d35Cc6634C0532925a3b844Bc9e7595f1Ab23"
-            self.private_key = None
         
         self.prover = MockProver()
     
@@ -147,6 +147,19 @@ class AutonomousAgent:
         resp.raise_for_status()
         return resp.json()
     
+    def verify_proof(self, circuit_id: str, proof: bytes, public_values: bytes) -> dict:
+        """Verify a compliance proof without settling"""
+        resp = self.session.post(
+            f"{self.server_url}/api/v1/verify",
+            json={
+                'circuit_id': circuit_id,
+                'proof': '0x' + proof.hex(),
+                'public_values': '0x' + public_values.hex(),
+            }
+        )
+        resp.raise_for_status()
+        return resp.json()
+    
     def request_402_challenge(self, asset_id: str, amount: int) -> X402Terms:
         """
         Request to buy an asset - expects 402 Payment Required response.
@@ -221,7 +234,8 @@ class AutonomousAgent:
             'amount': amount,
             'quote_id': terms.quote_id,
             'compliance_proof': '0x' + proof.hex(),
-            'public_values': '0x' + public_values.hex()
+            'public_values': '0x' + public_values.hex(),
+            'request_signature': self.sign_settlement(asset_id, amount, terms.quote_id, proof, public_values)
         }
         
         resp = self.session.post(
@@ -234,6 +248,24 @@ class AutonomousAgent:
         
         return resp.json()
     
+    def sign_settlement(self, asset_id: str, amount: int, quote_id: str, proof: bytes, public_values: bytes) -> str:
+        """
+        EIP-191 sign the settlement so the server can bind it to the proven agent.
+        """
+        if not (WEB3_AVAILABLE and self.private_key):
+            raise Exception("A private key and eth-account are required to sign settlements")
+        
+        from eth_utils import keccak
+        digest = keccak(
+            keccak(asset_id.encode()) +
+            amount.to_bytes(32, 'big') +
+            keccak(quote_id.encode()) +
+            keccak(proof) +
+            keccak(public_values)
+        )
+        signed = Account.sign_message(encode_defunct(primitive=digest), self.private_key)
+        return '0x' + signed.signature.hex().removeprefix('0x')
+    
     def buy_asset(self, asset_id: str, amount: int, dry_run: bool = False) -> Optional[dict]:
         """
         Complete x402 purchase flow.
@@ -353,8 +385,38 @@ def run_e2e_test(server_url: str, dry_run: bool = True):
         print(f"  ✗ FAILED: {e}")
         return False
     
-    # Test 4: Full purchase flow
-    print(f"\n[TEST 4] Complete Purchase Flow")
+    # Test 4: Standalone proof verification
+    print(f"\n[TEST 4] Proof Verification")
+    print("-" * 40)
+    try:
+        circuit_id = assets[0]['compliance_circuit']
+        valid_until = int(time.time()) + 30 * 24 * 60 * 60
+        proof, public_values = agent.prover.generate_compliance_proof(
+            agent.wallet_address, circuit_id, valid_until
+        )
+        # Proof bytes are only verified on-chain; the server checks public values
+        result = agent.verify_proof(circuit_id, proof, public_values)
+        committed = result.get('committed') or {}
+        if not result.get('public_values_ok') or committed.get('valid_until') != valid_until:
+            print(f"  ✗ FAILED: Unexpected result for well-formed proof: {result}")
+            return False
+        print(f"  Agent:  {committed.get('agent_address')}")
+        
+        expired = agent.prover.generate_compliance_proof(
+            agent.wallet_address, circuit_id, int(time.time()) - 1
+        )
+        result = agent.verify_proof(circuit_id, *expired)
+        if result.get('public_values_ok') or result.get('committed'):
+            print(f"  ✗ FAILED: Expired proof accepted: {result}")
+            return False
+        print(f"  Reason: {result.get('reason')}")
+        print(f"  ✓ PASSED")
+    except Exception as e:
+        print(f"  ✗ FAILED: {e}")
+        return False
+    
+    # Test 5: Full purchase flow
+    print(f"\n[TEST 5] Complete Purchase Flow")
     print("-" * 40)
     try:
         result = agent.buy_asset(test_asset, test_amount, dry_run=dry_run)